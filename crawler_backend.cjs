@@ -5,7 +5,8 @@ const cheerio = require('cheerio');
 const { JSDOM } = require('jsdom');
 
 const app = express();
-const PORT = 5002;
+// The Tauri app picks a free port and passes it in; 5002 is the dev default
+const PORT = Number(process.env.CRAWLER_PORT) || 5002;
 
 // Middleware
 app.use(cors());
@@ -224,7 +225,7 @@ app.get('/health', (req, res) => {
 // Start server
 app.listen(PORT, () => {
   console.log(`Crawler backend running on http://localhost:${PORT}`);
-  console.log('Health check: http://localhost:5002/health');
+  console.log(`Health check: http://localhost:${PORT}/health`);
 });
 
 module.exports = app; 
\ No newline at end of file
@@ -0,0 +1,179 @@
+// Credentials set with set_auth for APIs that want an Authorization header.
+// Each belongs to exactly one host and is only sent to it: never to its
+// subdomains, and never once a redirect leaves the host the request started
+// on. Downloads take a snapshot when they start, so changing the credentials
+// affects the next downloads rather than running ones.
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub(crate) enum Credential {
+  Bearer(String),
+  Basic { user: String, password: String },
+  // Answered to the server's challenge rather than sent up front; see digest.rs
+  Digest { user: String, password: String },
+}
+
+impl Credential {
+  // "bearer" takes the token itself, "basic" and "digest" a "user:password"
+  // pair
+  pub(crate) fn parse(scheme: &str, token: &str) -> Result<Self, String> {
+    if token.is_empty() || token.chars().any(|c| c.is_control()) {
+      return Err("Credentials must be non-empty and free of control characters".to_string());
+    }
+    match scheme.trim().to_ascii_lowercase().as_str() {
+      "bearer" => {
+        if token.contains(char::is_whitespace) {
+          return Err("Bearer tokens can't contain whitespace".to_string());
+        }
+        Ok(Self::Bearer(token.to_string()))
+      }
+      "basic" | "digest" => {
+        let (user, password) = token
+          .split_once(':')
+          .ok_or("Basic and Digest credentials must be given as user:password")?;
+        let (user, password) = (user.to_string(), password.to_string());
+        if scheme.trim().eq_ignore_ascii_case("digest") {
+          Ok(Self::Digest { user, password })
+        } else {
+          Ok(Self::Basic { user, password })
+        }
+      }
+      other => Err(format!("Unsupported authentication scheme {:?}; use bearer, basic or digest", other)),
+    }
+  }
+
+  pub(crate) fn scheme(&self) -> &'static str {
+    match self {
+      Self::Bearer(_) => "bearer",
+      Self::Basic { .. } => "basic",
+      Self::Digest { .. } => "digest",
+    }
+  }
+
+  fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match self {
+      Self::Bearer(token) => request.bearer_auth(token),
+      Self::Basic { user, password } => request.basic_auth(user, Some(password)),
+      Self::Digest { .. } => request,
+    }
+  }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct AuthStore {
+  by_host: HashMap<String, Credential>,
+}
+
+impl AuthStore {
+  // Sets or, with None, removes the credential of a host
+  pub(crate) fn set(&mut self, host: &str, credential: Option<Credential>) -> Result<(), String> {
+    let host = crate::hosts::normalize(host);
+    if host.is_empty() || (host.contains(['/', ':', '@']) && host.parse::<std::net::Ipv6Addr>().is_err()) {
+      return Err(format!("{:?} is not a host name", host));
+    }
+    match credential {
+      Some(credential) => self.by_host.insert(host, credential),
+      None => self.by_host.remove(&host),
+    };
+    Ok(())
+  }
+
+  fn get(&self, host: &str) -> Option<&Credential> {
+    self.by_host.get(&crate::hosts::normalize(host))
+  }
+
+  // Adds the Authorization header to a request for `current`, which a request
+  // for `original` was redirected to (or is, when not redirected). Nothing is
+  // added unless both are the host the credential was set for.
+  pub(crate) fn apply(
+    &self,
+    original: &url::Url,
+    current: &url::Url,
+    request: reqwest::RequestBuilder,
+  ) -> reqwest::RequestBuilder {
+    match self.for_request(original, current) {
+      Some(credential) => credential.apply(request),
+      None => request,
+    }
+  }
+
+  // The Authorization header answering the Digest challenge of a 401 response
+  // to a `method` request for `current`, under the same host rules as apply.
+  // None when the host has no Digest credentials or the server didn't ask for
+  // Digest; an error when it asked in a form that isn't supported.
+  pub(crate) fn digest(
+    &self,
+    original: &url::Url,
+    current: &url::Url,
+    method: &reqwest::Method,
+    challenges: &reqwest::header::HeaderMap,
+  ) -> Result<Option<String>, String> {
+    let Some(Credential::Digest { user, password }) = self.for_request(original, current) else {
+      return Ok(None);
+    };
+    let values = challenges
+      .get_all(reqwest::header::WWW_AUTHENTICATE)
+      .iter()
+      .filter_map(|value| value.to_str().ok());
+    let Some(challenge) = crate::digest::Challenge::find(values)? else {
+      return Ok(None);
+    };
+    let uri = match current.query() {
+      Some(query) => format!("{}?{}", current.path(), query),
+      None => current.path().to_string(),
+    };
+    Ok(Some(challenge.respond(user, password, method.as_str(), &uri, &crate::digest::cnonce())))
+  }
+
+  fn for_request(&self, original: &url::Url, current: &url::Url) -> Option<&Credential> {
+    let (original, current) = (original.host_str()?, current.host_str()?);
+    self
+      .get(current)
+      .filter(|_| crate::hosts::normalize(original) == crate::hosts::normalize(current))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn header(store: &AuthStore, original: &str, current: &str) -> Option<String> {
+    let request = reqwest::Client::new().get(current);
+    let request = store
+      .apply(&url::Url::parse(original).unwrap(), &url::Url::parse(current).unwrap(), request)
+      .build()
+      .unwrap();
+    request
+      .headers()
+      .get(reqwest::header::AUTHORIZATION)
+      .map(|value| value.to_str().unwrap().to_string())
+  }
+
+  #[test]
+  fn credentials_stay_on_their_host() {
+    let mut store = AuthStore::default();
+    store.set("API.example.com", Some(Credential::parse("Bearer", "abc123").unwrap())).unwrap();
+    store.set("files.example.com", Some(Credential::parse("basic", "user:pa:ss").unwrap())).unwrap();
+
+    let api = "https://api.example.com/file";
+    assert_eq!(header(&store, api, api).as_deref(), Some("Bearer abc123"));
+    assert_eq!(header(&store, api, "https://cdn.example.net/file"), None);
+    assert_eq!(header(&store, "https://other.example.com/", api), None);
+    assert_eq!(header(&store, "https://x.api.example.com/", "https://x.api.example.com/"), None);
+    let files = "https://files.example.com/a";
+    assert_eq!(header(&store, files, files).as_deref(), Some("Basic dXNlcjpwYTpzcw=="));
+
+    store.set("api.example.com", None).unwrap();
+    assert_eq!(header(&store, api, api), None);
+  }
+
+  #[test]
+  fn rejects_malformed_credentials() {
+    assert!(Credential::parse("bearer", "").is_err());
+    assert!(Credential::parse("bearer", "two words").is_err());
+    assert!(Credential::parse("basic", "nocolon").is_err());
+    assert!(Credential::parse("digest", "x").is_err());
+    assert!(Credential::parse("ntlm", "user:pass").is_err());
+    assert!(AuthStore::default().set("https://example.com/", None).is_err());
+  }
+}
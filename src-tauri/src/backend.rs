@@ -0,0 +1,442 @@
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{Ipv4Addr, TcpListener};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+use crate::error::DownloadError;
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState};
+
+// Port tried first unless STREAM_HAVEN_CRAWLER_PORT says otherwise
+const DEFAULT_PORT: u16 = 5002;
+// Ports tried after the preferred one when it is already taken
+const PORT_SEARCH_RANGE: u16 = 50;
+// How often the supervisor checks whether the crawler is still alive
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// An exit sooner than this after launch counts as a rapid failure
+const RAPID_FAILURE_WINDOW: Duration = Duration::from_secs(10);
+// Rapid failures in a row before giving up and reporting "degraded"
+const MAX_RAPID_FAILURES: u32 = 5;
+const RESTART_BASE_DELAY: Duration = Duration::from_secs(1);
+const RESTART_MAX_DELAY: Duration = Duration::from_secs(30);
+// How long the crawler gets to exit after SIGTERM before it is killed
+#[cfg(unix)]
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+// How long a health check may take before the crawler counts as not ready
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+// How long startup waits for the first successful health check
+const STARTUP_READY_TIMEOUT: Duration = Duration::from_secs(20);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Crawler status reported by get_backend_status. `state` is one of
+// "starting", "running", "restarting", "idle" (stopped after going unused,
+// see set_backend_idle_timeout) or "degraded".
+#[derive(Serialize, Clone)]
+pub(crate) struct BackendStatus {
+  pub state: String,
+  pub pid: Option<u32>,
+  // Port the current crawler was launched on
+  pub port: Option<u16>,
+  pub restarts: u32,
+  pub last_exit: Option<String>,
+}
+
+// Result of a health check. A crawler that is running but hasn't bound its
+// port yet is not ready, with the reason in `error`.
+#[derive(Serialize, Clone)]
+pub(crate) struct BackendReadiness {
+  pub ready: bool,
+  pub port: Option<u16>,
+  // Round trip of the health request, when it got an answer
+  pub latency_ms: Option<u64>,
+  pub error: Option<String>,
+}
+
+// Owns the crawler process and its reported status
+pub(crate) struct Backend {
+  child: Mutex<Option<Child>>,
+  status: Mutex<BackendStatus>,
+  // Set on app exit so the supervisor stops relaunching
+  shutting_down: AtomicBool,
+  idle: Mutex<Idle>,
+  // Wakes a supervisor waiting with the crawler stopped for idleness
+  needed: Condvar,
+}
+
+struct Idle {
+  // Unused time after which the crawler is stopped; None keeps it running
+  timeout: Option<Duration>,
+  last_used: Instant,
+  // Set when the stopped crawler is wanted again
+  wanted: bool,
+}
+
+impl Backend {
+  pub(crate) fn new() -> Self {
+    Self {
+      child: Mutex::new(None),
+      status: Mutex::new(BackendStatus {
+        state: "starting".to_string(),
+        pid: None,
+        port: None,
+        restarts: 0,
+        last_exit: None,
+      }),
+      shutting_down: AtomicBool::new(false),
+      idle: Mutex::new(Idle { timeout: None, last_used: Instant::now(), wanted: false }),
+      needed: Condvar::new(),
+    }
+  }
+
+  pub(crate) fn set_idle_timeout(&self, timeout: Option<Duration>) {
+    let mut idle = self.idle.lock_or_recover();
+    idle.timeout = timeout;
+    idle.last_used = Instant::now();
+  }
+
+  // Records that the crawler is being used, restarting it if it was stopped
+  // for idleness. Returns whether it has to start up again.
+  fn touch(&self) -> bool {
+    let mut idle = self.idle.lock_or_recover();
+    idle.last_used = Instant::now();
+    if self.status.lock_or_recover().state != "idle" {
+      return false;
+    }
+    if !idle.wanted {
+      log::info!("[crawler] needed again; restarting");
+      idle.wanted = true;
+      self.needed.notify_all();
+    }
+    true
+  }
+
+  // The crawler's URL for a caller about to use it. A crawler stopped for
+  // idleness is started again first, waiting until it answers health checks.
+  pub(crate) async fn url_for_use(&self) -> Result<String, DownloadError> {
+    if self.touch() {
+      let deadline = Instant::now() + STARTUP_READY_TIMEOUT;
+      while !self.check_ready().await.ready && Instant::now() < deadline {
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+      }
+    }
+    self.url()
+  }
+
+  fn idle_expired(&self) -> bool {
+    let idle = self.idle.lock_or_recover();
+    idle.timeout.is_some_and(|timeout| idle.last_used.elapsed() >= timeout)
+  }
+
+  // Stops the crawler for idleness and blocks until it is wanted again.
+  // Returns false if the app is exiting instead.
+  fn sleep_until_needed(&self) -> bool {
+    let mut idle = self.idle.lock_or_recover();
+    // Checked again under the lock so a use that just came in isn't missed
+    if !idle.timeout.is_some_and(|timeout| idle.last_used.elapsed() >= timeout) {
+      return true;
+    }
+    idle.wanted = false;
+    self.update_status(|status| {
+      status.state = "idle".to_string();
+      status.pid = None;
+      status.port = None;
+    });
+    let timeout = idle.timeout.unwrap_or_default();
+    // Stopping can take up to SHUTDOWN_GRACE, so it happens without the lock
+    // and touch() isn't kept waiting. A use in the meantime sets wanted, which
+    // the loop below sees once the lock is back.
+    drop(idle);
+    let child = self.child.lock_or_recover().take();
+    if let Some(child) = child {
+      log::info!("[crawler] unused for {:?}; stopping pid {}", timeout, child.id());
+      terminate(child);
+    }
+    let mut idle = self.idle.lock_or_recover();
+    while !idle.wanted && !self.is_shutting_down() {
+      idle = self.needed.wait(idle).unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+    !self.is_shutting_down()
+  }
+
+  pub(crate) fn status(&self) -> Result<BackendStatus, String> {
+    Ok(self.status.lock_or_recover().clone())
+  }
+
+  pub(crate) fn url(&self) -> Result<String, DownloadError> {
+    match self.status()?.port {
+      Some(port) => Ok(format!("http://127.0.0.1:{}", port)),
+      None => Err(DownloadError::InvalidState("Crawler backend is not running".to_string())),
+    }
+  }
+
+  // Asks the crawler's /health endpoint whether it is serving requests yet
+  pub(crate) async fn check_ready(&self) -> BackendReadiness {
+    let port = self.status.lock_or_recover().port;
+    let not_ready = |error: String| BackendReadiness { ready: false, port, latency_ms: None, error: Some(error) };
+    let Some(port) = port else {
+      return not_ready("Crawler backend is not running".to_string());
+    };
+    // Its own client: the crawler is local, so no proxy or DNS checks apply
+    let client = match reqwest::Client::builder().no_proxy().timeout(HEALTH_TIMEOUT).build() {
+      Ok(client) => client,
+      Err(e) => return not_ready(format!("Failed to create HTTP client: {}", e)),
+    };
+    let started = Instant::now();
+    match client.get(format!("http://127.0.0.1:{}/health", port)).send().await {
+      Ok(response) if response.status().is_success() => BackendReadiness {
+        ready: true,
+        port: Some(port),
+        latency_ms: Some(started.elapsed().as_millis() as u64),
+        error: None,
+      },
+      Ok(response) => BackendReadiness {
+        latency_ms: Some(started.elapsed().as_millis() as u64),
+        ..not_ready(format!("Health check answered {}", response.status()))
+      },
+      Err(e) if e.is_timeout() => not_ready(format!("Health check timed out after {:?}", HEALTH_TIMEOUT)),
+      Err(e) => not_ready(format!("Health check failed: {}", e)),
+    }
+  }
+
+  fn update_status<F: FnOnce(&mut BackendStatus)>(&self, f: F) {
+    f(&mut self.status.lock_or_recover());
+  }
+
+  // Stops the crawler for good
+  pub(crate) fn shutdown(&self) {
+    self.shutting_down.store(true, Ordering::SeqCst);
+    {
+      let _idle = self.idle.lock_or_recover();
+      self.needed.notify_all();
+    }
+    let child = self.child.lock_or_recover().take();
+    let Some(child) = child else { return };
+    log::info!("[crawler] stopping pid {}", child.id());
+    terminate(child);
+  }
+
+  fn is_shutting_down(&self) -> bool {
+    self.shutting_down.load(Ordering::SeqCst)
+  }
+
+  // Returns the exit description once the held child has exited
+  fn poll_exit(&self) -> Option<String> {
+    let mut child = self.child.lock_or_recover();
+    let exit = match child.as_mut()?.try_wait() {
+      Ok(Some(status)) => status.to_string(),
+      Ok(None) => return None,
+      Err(e) => format!("failed to wait: {}", e),
+    };
+    *child = None;
+    Some(exit)
+  }
+}
+
+// Ends a crawler process. On Unix it gets SIGTERM and a grace period before
+// being killed; elsewhere it is killed right away.
+fn terminate(mut child: Child) {
+  #[cfg(unix)]
+  {
+    // SAFETY: kill() has no memory-safety preconditions; the pid belongs to
+    // our own child, which can't be reaped until we wait on it below
+    if let Ok(pid) = i32::try_from(child.id()) {
+      unsafe {
+        libc::kill(pid, libc::SIGTERM);
+      }
+    }
+    let deadline = Instant::now() + SHUTDOWN_GRACE;
+    while Instant::now() < deadline {
+      if let Ok(Some(_)) = child.try_wait() {
+        return;
+      }
+      std::thread::sleep(Duration::from_millis(50));
+    }
+    log::warn!("[crawler] did not exit after SIGTERM; killing it");
+  }
+
+  if let Err(e) = child.kill() {
+    log::warn!("[crawler] failed to kill: {}", e);
+  }
+  let _ = child.wait();
+}
+
+// Keeps the crawler running for the lifetime of the app. An unexpected exit is
+// followed by a relaunch after an exponential backoff; after
+// MAX_RAPID_FAILURES quick failures in a row the supervisor stops and the
+// backend is reported as degraded. A crawler left unused for the idle
+// timeout is stopped here too, which isn't an exit to recover from: it is
+// launched again once url_for_use asks for it.
+pub(crate) fn supervise(app: AppHandle) {
+  let backend = &app.state::<AppState>().backend;
+  let mut rapid_failures = 0;
+
+  'launch: loop {
+    let started = Instant::now();
+    // Picked on every launch in case another process took the old port
+    let spawned = pick_port().and_then(|port| Ok((port, spawn_crawler(port)?)));
+    let exit = match spawned {
+      Ok((port, mut child)) => {
+        let pid = child.id();
+        {
+          let mut slot = backend.child.lock_or_recover();
+          // The app began exiting while the crawler was launching
+          if backend.is_shutting_down() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return;
+          }
+          *slot = Some(child);
+        }
+        backend.update_status(|status| {
+          status.state = "running".to_string();
+          status.pid = Some(pid);
+          status.port = Some(port);
+        });
+        loop {
+          std::thread::sleep(POLL_INTERVAL);
+          if backend.is_shutting_down() {
+            return;
+          }
+          if let Some(exit) = backend.poll_exit() {
+            break exit;
+          }
+          if backend.idle_expired() {
+            if !backend.sleep_until_needed() {
+              return;
+            }
+            if backend.status.lock_or_recover().state == "idle" {
+              rapid_failures = 0;
+              continue 'launch;
+            }
+          }
+        }
+      }
+      Err(e) => format!("failed to start: {}", e),
+    };
+    if backend.is_shutting_down() {
+      return;
+    }
+    log::warn!("[crawler] exited unexpectedly: {}", exit);
+
+    if started.elapsed() < RAPID_FAILURE_WINDOW {
+      rapid_failures += 1;
+    } else {
+      rapid_failures = 1;
+    }
+    if rapid_failures >= MAX_RAPID_FAILURES {
+      log::error!(
+        "[crawler] failed {} times in a row; giving up on restarts",
+        rapid_failures
+      );
+      backend.update_status(|status| {
+        status.state = "degraded".to_string();
+        status.pid = None;
+        status.port = None;
+        status.last_exit = Some(exit);
+      });
+      return;
+    }
+
+    backend.update_status(|status| {
+      status.state = "restarting".to_string();
+      status.pid = None;
+      status.port = None;
+      status.restarts += 1;
+      status.last_exit = Some(exit);
+    });
+    let delay = RESTART_BASE_DELAY
+      .saturating_mul(1 << (rapid_failures - 1))
+      .min(RESTART_MAX_DELAY);
+    std::thread::sleep(delay);
+    if backend.is_shutting_down() {
+      return;
+    }
+  }
+}
+
+// Waits for the freshly launched crawler to answer its health check, so the
+// frontend hears "backend://ready" (with the BackendReadiness) instead of
+// racing node while it binds its port. Gives up after STARTUP_READY_TIMEOUT
+// and announces the last failed check instead.
+pub(crate) async fn announce_ready(app: AppHandle) {
+  let deadline = Instant::now() + STARTUP_READY_TIMEOUT;
+  let readiness = loop {
+    let readiness = app.state::<AppState>().backend.check_ready().await;
+    if readiness.ready || Instant::now() >= deadline {
+      break readiness;
+    }
+    tokio::time::sleep(READY_POLL_INTERVAL).await;
+  };
+  match &readiness {
+    BackendReadiness { ready: true, latency_ms, .. } => {
+      log::info!("[crawler] ready ({} ms health check)", latency_ms.unwrap_or_default());
+    }
+    BackendReadiness { error, .. } => {
+      log::warn!("[crawler] not ready after {:?}: {}", STARTUP_READY_TIMEOUT, error.as_deref().unwrap_or_default());
+    }
+  }
+  if let Err(e) = app.emit("backend://ready", &readiness) {
+    log::warn!("Failed to emit backend readiness: {}", e);
+  }
+}
+
+// Returns the first free port starting at the preferred one, taken from
+// STREAM_HAVEN_CRAWLER_PORT when set.
+fn pick_port() -> std::io::Result<u16> {
+  let preferred = match std::env::var("STREAM_HAVEN_CRAWLER_PORT") {
+    Ok(value) => value.parse().unwrap_or_else(|_| {
+      log::warn!("[crawler] ignoring invalid STREAM_HAVEN_CRAWLER_PORT {:?}", value);
+      DEFAULT_PORT
+    }),
+    Err(_) => DEFAULT_PORT,
+  };
+  (0..=PORT_SEARCH_RANGE)
+    .filter_map(|offset| preferred.checked_add(offset))
+    .find(|port| TcpListener::bind((Ipv4Addr::LOCALHOST, *port)).is_ok())
+    .ok_or_else(|| {
+      std::io::Error::new(
+        std::io::ErrorKind::AddrInUse,
+        format!("no free port in {}..={}", preferred, preferred.saturating_add(PORT_SEARCH_RANGE)),
+      )
+    })
+}
+
+// Launches the node crawler backend with its output captured, so crashes and
+// warnings end up in the app log instead of disappearing.
+fn spawn_crawler(port: u16) -> std::io::Result<Child> {
+  let mut child = Command::new("node")
+    .arg("crawler_backend.cjs")
+    .current_dir("..") // Adjust if needed
+    .env("CRAWLER_PORT", port.to_string())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()?;
+
+  if let Some(stdout) = child.stdout.take() {
+    forward_output(stdout, log::Level::Info);
+  }
+  if let Some(stderr) = child.stderr.take() {
+    forward_output(stderr, log::Level::Warn);
+  }
+  log::info!("[crawler] started with pid {} on port {}", child.id(), port);
+  Ok(child)
+}
+
+// Logs each line of a child pipe on its own thread until the pipe closes.
+fn forward_output<R: Read + Send + 'static>(pipe: R, level: log::Level) {
+  std::thread::spawn(move || {
+    for line in BufReader::new(pipe).lines() {
+      match line {
+        Ok(line) => log::log!(level, "[crawler] {}", line),
+        Err(e) => {
+          log::warn!("[crawler] failed to read output: {}", e);
+          break;
+        }
+      }
+    }
+  });
+}
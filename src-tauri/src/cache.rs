@@ -0,0 +1,176 @@
+// Small in-memory cache for fetch_text and fetch_json, so metadata endpoints
+// the frontend polls aren't fetched again while still fresh. Entries live as
+// long as the response's Cache-Control max-age allows (DEFAULT_TTL without
+// one, never with no-store or no-cache), and the least recently used ones
+// are evicted once the bodies exceed the byte budget. Bodies above
+// MAX_ENTRY_BYTES are never cached.
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderMap, CACHE_CONTROL};
+use serde::Serialize;
+
+pub(crate) const DEFAULT_CAPACITY: usize = 4 * 1024 * 1024;
+pub(crate) const MAX_CAPACITY: usize = 256 * 1024 * 1024;
+pub(crate) const MAX_ENTRY_BYTES: usize = 256 * 1024;
+// Freshness of responses that don't say
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+// A fetched text response, as returned to the frontend
+#[derive(Serialize, Clone)]
+pub(crate) struct TextResponse {
+  // Where the request ended up after redirects
+  pub url: String,
+  pub status: u16,
+  pub content_type: Option<String>,
+  pub body: String,
+  // Whether this came from the cache instead of the network
+  pub cached: bool,
+}
+
+struct Entry {
+  response: TextResponse,
+  expires_at: Instant,
+}
+
+pub(crate) struct ResponseCache {
+  entries: HashMap<String, Entry>,
+  // Keys from least to most recently used
+  order: VecDeque<String>,
+  bytes: usize,
+  // Zero turns the cache off
+  capacity: usize,
+}
+
+impl ResponseCache {
+  pub(crate) fn new(capacity: usize) -> Self {
+    Self { entries: HashMap::new(), order: VecDeque::new(), bytes: 0, capacity }
+  }
+
+  pub(crate) fn get(&mut self, key: &str, now: Instant) -> Option<TextResponse> {
+    match self.entries.get(key) {
+      Some(entry) if entry.expires_at > now => {
+        let response = TextResponse { cached: true, ..entry.response.clone() };
+        self.touch(key);
+        Some(response)
+      }
+      Some(_) => {
+        self.remove(key);
+        None
+      }
+      None => None,
+    }
+  }
+
+  // Stores a response for `ttl`, evicting the least recently used entries to
+  // make room. Responses that are too big or not to be kept are skipped.
+  pub(crate) fn insert(&mut self, key: String, response: TextResponse, ttl: Duration, now: Instant) {
+    let size = response.body.len();
+    self.remove(&key);
+    if ttl.is_zero() || size > MAX_ENTRY_BYTES || size > self.capacity {
+      return;
+    }
+    while self.bytes + size > self.capacity {
+      let Some(oldest) = self.order.front().cloned() else { break };
+      self.remove(&oldest);
+    }
+    self.bytes += size;
+    self.order.push_back(key.clone());
+    self.entries.insert(key, Entry { response, expires_at: now + ttl });
+  }
+
+  // Changes the byte budget, evicting what no longer fits. Zero empties and
+  // disables the cache.
+  pub(crate) fn set_capacity(&mut self, capacity: usize) {
+    self.capacity = capacity;
+    while self.bytes > self.capacity {
+      let Some(oldest) = self.order.front().cloned() else { break };
+      self.remove(&oldest);
+    }
+  }
+
+  // Empties the cache and returns how many entries it held
+  pub(crate) fn clear(&mut self) -> usize {
+    let count = self.entries.len();
+    self.entries.clear();
+    self.order.clear();
+    self.bytes = 0;
+    count
+  }
+
+  fn touch(&mut self, key: &str) {
+    if let Some(position) = self.order.iter().position(|entry| entry == key) {
+      if let Some(key) = self.order.remove(position) {
+        self.order.push_back(key);
+      }
+    }
+  }
+
+  fn remove(&mut self, key: &str) {
+    if let Some(entry) = self.entries.remove(key) {
+      self.bytes -= entry.response.body.len();
+      self.order.retain(|entry| entry != key);
+    }
+  }
+}
+
+// How long a response may be served from the cache according to its
+// Cache-Control header. Zero means it must not be cached.
+pub(crate) fn freshness(headers: &HeaderMap) -> Duration {
+  let directives: Vec<String> = headers
+    .get_all(CACHE_CONTROL)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(|directive| directive.trim().to_ascii_lowercase())
+    .collect();
+  if directives.iter().any(|directive| directive == "no-store" || directive == "no-cache") {
+    return Duration::ZERO;
+  }
+  directives
+    .iter()
+    .find_map(|directive| directive.strip_prefix("max-age="))
+    .map(|secs| Duration::from_secs(secs.trim_matches('"').parse().unwrap_or(0)))
+    .unwrap_or(DEFAULT_TTL)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn response(body: &str) -> TextResponse {
+    TextResponse { url: String::new(), status: 200, content_type: None, body: body.to_string(), cached: false }
+  }
+
+  #[test]
+  fn evicts_least_recently_used_and_expired_entries() {
+    let now = Instant::now();
+    let ttl = Duration::from_secs(10);
+    let mut cache = ResponseCache::new(10);
+    cache.insert("a".to_string(), response("aaaa"), ttl, now);
+    cache.insert("b".to_string(), response("bbbb"), ttl, now);
+    assert!(cache.get("a", now).is_some_and(|hit| hit.cached));
+    // "b" is now the least recently used, so it makes room for "c"
+    cache.insert("c".to_string(), response("cccc"), ttl, now);
+    assert!(cache.get("b", now).is_none());
+    assert!(cache.get("a", now).is_some());
+    assert!(cache.get("c", now + ttl).is_none());
+
+    cache.insert("big".to_string(), response("0123456789a"), ttl, now);
+    assert!(cache.get("big", now).is_none());
+    assert_eq!(cache.clear(), 1);
+  }
+
+  #[test]
+  fn reads_cache_control() {
+    let headers = |value: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(CACHE_CONTROL, value.parse().unwrap());
+      headers
+    };
+    assert_eq!(freshness(&headers("public, max-age=300")), Duration::from_secs(300));
+    assert_eq!(freshness(&headers("no-store")), Duration::ZERO);
+    assert_eq!(freshness(&headers("max-age=60, no-cache")), Duration::ZERO);
+    assert_eq!(freshness(&HeaderMap::new()), DEFAULT_TTL);
+  }
+}
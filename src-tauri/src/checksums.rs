@@ -0,0 +1,105 @@
+// Checksum manifests in the format sha256sum writes and release pages publish
+// as SHA256SUMS: one "<hex hash>  <file name>" line per file, with "*" in
+// place of the second space for files hashed in binary mode. Names are
+// matched by their last path component, since manifests often list them as
+// "./name" or under a subdirectory.
+use std::collections::HashMap;
+
+// Longest manifest accepted; real ones list a few hundred files at most
+pub(crate) const MAX_MANIFEST_BYTES: usize = 1024 * 1024;
+
+// Expected lowercase hex SHA-256 by file name. Blank lines and "#" comments
+// are skipped; any other line that isn't an entry is an error, as is a name
+// listed twice with different hashes.
+pub(crate) fn parse(text: &str) -> Result<HashMap<String, String>, String> {
+  if text.len() > MAX_MANIFEST_BYTES {
+    return Err(format!("Checksum manifest is over {} bytes", MAX_MANIFEST_BYTES));
+  }
+  let mut entries = HashMap::new();
+  for (number, line) in text.lines().enumerate() {
+    let line = line.trim_end_matches('\r');
+    if line.trim().is_empty() || line.trim_start().starts_with('#') {
+      continue;
+    }
+    let (hash, name) =
+      entry(line).ok_or_else(|| format!("Line {} of the checksum manifest is not \"HASH  filename\"", number + 1))?;
+    match entries.insert(name.clone(), hash.clone()) {
+      Some(other) if other != hash => {
+        return Err(format!("Checksum manifest lists {} twice with different hashes", name));
+      }
+      _ => {}
+    }
+  }
+  if entries.is_empty() {
+    return Err("Checksum manifest has no entries".to_string());
+  }
+  Ok(entries)
+}
+
+fn entry(line: &str) -> Option<(String, String)> {
+  // sha256sum escapes names containing a backslash or newline and marks the
+  // line with a leading backslash
+  let (escaped, line) = match line.strip_prefix('\\') {
+    Some(line) => (true, line),
+    None => (false, line),
+  };
+  let (hash, rest) = line.split_once(' ')?;
+  let name = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+  if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+    return None;
+  }
+  let name = if escaped { unescape(name) } else { name.to_string() };
+  let name = name.rsplit('/').next().unwrap_or_default().to_string();
+  (!name.is_empty()).then(|| (hash.to_ascii_lowercase(), name))
+}
+
+// Undoes sha256sum's escapes, \n for a newline and \\ for a backslash
+fn unescape(name: &str) -> String {
+  let mut unescaped = String::with_capacity(name.len());
+  let mut chars = name.chars();
+  while let Some(c) = chars.next() {
+    match (c, chars.clone().next()) {
+      ('\\', Some('n')) => unescaped.push('\n'),
+      ('\\', Some('\\')) => unescaped.push('\\'),
+      _ => {
+        unescaped.push(c);
+        continue;
+      }
+    }
+    chars.next();
+  }
+  unescaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+  #[test]
+  fn parses_sha256sums_lines() {
+    let upper = HASH.to_ascii_uppercase();
+    let text = format!(
+      "# release 1.2\n{}  app.tar.gz\r\n\n{} *./bin/app.exe\n\\{}  dir/odd\\\\name\n",
+      HASH, upper, HASH
+    );
+    let entries = parse(&text).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries["app.tar.gz"], HASH);
+    assert_eq!(entries["app.exe"], HASH);
+    assert_eq!(entries["odd\\name"], HASH);
+  }
+
+  #[test]
+  fn rejects_malformed_manifests() {
+    assert!(parse("").is_err());
+    assert!(parse("# only a comment\n").is_err());
+    assert!(parse(&format!("{} app.tar.gz", HASH)).is_err());
+    assert!(parse("abc123  app.tar.gz").is_err());
+    assert!(parse(&format!("{}  ", HASH)).is_err());
+    let other = "0".repeat(64);
+    assert!(parse(&format!("{}  a.zip\n{}  a.zip\n", HASH, other)).is_err());
+    assert!(parse(&format!("{}  a.zip\n{}  ./a.zip\n", HASH, HASH)).is_ok());
+  }
+}
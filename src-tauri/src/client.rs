@@ -0,0 +1,139 @@
+// The one reqwest client all downloads share, so files fetched from the same
+// host reuse pooled connections and TLS sessions instead of handshaking for
+// every download. It is built from the proxy, TLS and timeout settings on
+// first use and dropped by the commands that change them, so the next request
+// builds a new one while running transfers keep theirs. Headers, cookies and
+// credentials differ per download and are only ever set on requests.
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Deserialize;
+
+use crate::lock::LockExt;
+use crate::AppState;
+
+// Sent unless the download's headers set their own User-Agent
+const USER_AGENT: &str = concat!("StreamHaven/", env!("CARGO_PKG_VERSION"));
+
+// Which address families connections may use, for networks where one of
+// them is broken. Auto connects to whichever answers first, preferring the
+// first address DNS returns and falling back to the other family shortly
+// after (happy eyeballs).
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum IpVersion {
+  #[default]
+  Auto,
+  V4Only,
+  V6Only,
+}
+
+impl IpVersion {
+  // Drops the addresses of the family not to be used
+  pub(crate) fn filter(self, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    addrs
+      .into_iter()
+      .filter(|addr| match self {
+        IpVersion::Auto => true,
+        IpVersion::V4Only => addr.is_ipv4(),
+        IpVersion::V6Only => addr.is_ipv6(),
+      })
+      .collect()
+  }
+}
+
+#[derive(Default)]
+pub(crate) struct SharedClient {
+  client: Mutex<Option<reqwest::Client>>,
+}
+
+impl SharedClient {
+  // The current client, built first if the settings changed since the last one
+  pub(crate) fn get(&self, state: &AppState) -> Result<reqwest::Client, String> {
+    let mut client = self.client.lock_or_recover();
+    if let Some(client) = &*client {
+      return Ok(client.clone());
+    }
+    let built = build(state)?;
+    *client = Some(built.clone());
+    Ok(built)
+  }
+
+  // Called after a setting the client is built from has changed
+  pub(crate) fn invalidate(&self) {
+    *self.client.lock_or_recover() = None;
+  }
+}
+
+fn build(state: &AppState) -> Result<reqwest::Client, String> {
+  let proxy = state.proxy.lock_or_recover().clone();
+  let tls = state.tls.lock_or_recover().clone();
+  let connect_timeout = Duration::from_secs(state.connect_timeout_secs.load(Ordering::Relaxed));
+  let read_timeout = Duration::from_secs(state.stall_timeout_secs.load(Ordering::Relaxed));
+  let ip_version = *state.ip_version.lock_or_recover();
+  let proxy_host = proxy
+    .as_deref()
+    .and_then(|proxy| url::Url::parse(proxy).ok())
+    .and_then(|proxy| proxy.host_str().map(crate::hosts::normalize));
+  // Redirects are followed by ConnectionPool::fetch so each hop gets validated
+  let mut builder = reqwest::Client::builder()
+    .user_agent(USER_AGENT)
+    .connect_timeout(connect_timeout)
+    .read_timeout(read_timeout)
+    .redirect(reqwest::redirect::Policy::none())
+    .dns_resolver(Arc::new(PublicResolver { proxy_host, ip_version }));
+  if let Some(proxy) = &proxy {
+    let proxy = reqwest::Proxy::all(proxy.as_str()).map_err(|e| format!("Invalid proxy: {}", e))?;
+    builder = builder.proxy(proxy);
+  }
+  tls
+    .apply(builder)
+    .build()
+    .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+// Resolves host names for the shared client and refuses any that point at an
+// internal address. Hosts are checked before a download starts as well, but
+// checking again here covers the lookup the connection actually uses, so a
+// DNS answer that changes in between can't reach the local network. The
+// configured proxy is exempt, since it is often on localhost. Only addresses
+// of the families set_ip_version_preference allows are returned, and those
+// are the ones checked.
+struct PublicResolver {
+  proxy_host: Option<String>,
+  ip_version: IpVersion,
+}
+
+impl Resolve for PublicResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    let host = crate::hosts::normalize(name.as_str());
+    let exempt = self.proxy_host.as_deref() == Some(host.as_str());
+    let ip_version = self.ip_version;
+    Box::pin(async move {
+      // The port is filled in by the connector
+      let addrs = ip_version.filter(tokio::net::lookup_host((host.as_str(), 0)).await?.collect());
+      if !exempt {
+        crate::check_resolved_addrs(&host, &addrs)?;
+      }
+      Ok(Box::new(addrs.into_iter()) as Addrs)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ip_version_keeps_one_family() {
+    let v4: SocketAddr = "93.184.216.34:443".parse().unwrap();
+    let v6: SocketAddr = "[2606:2800:220:1::1]:443".parse().unwrap();
+    assert_eq!(IpVersion::Auto.filter(vec![v6, v4]), vec![v6, v4]);
+    assert_eq!(IpVersion::V4Only.filter(vec![v6, v4]), vec![v4]);
+    assert_eq!(IpVersion::V6Only.filter(vec![v6, v4]), vec![v6]);
+    assert!(IpVersion::V6Only.filter(vec![v4]).is_empty());
+  }
+}
@@ -0,0 +1,109 @@
+// Opt-in clipboard watcher, turned on with set_clipboard_watch. While on, the
+// clipboard is polled for a copied link the downloader would accept, and each
+// new one is announced as a "download://clipboard-url" event so the frontend
+// can offer to download it. Nothing is downloaded without the user.
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tokio_util::sync::CancellationToken;
+
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Longer clipboard contents are text, not a link
+const MAX_URL_LENGTH: usize = 8 * 1024;
+
+#[derive(Serialize, Clone)]
+struct ClipboardUrl<'a> {
+  url: &'a str,
+}
+
+// What the watcher has already seen, so a link stays on the clipboard (or is
+// copied again) without being offered twice in a row
+#[derive(Default)]
+struct Seen {
+  text: Option<String>,
+  offered: Option<String>,
+}
+
+impl Seen {
+  // The link to offer for the clipboard now holding `text`, if any
+  fn next(&mut self, text: &str) -> Option<String> {
+    if self.text.as_deref() == Some(text) {
+      return None;
+    }
+    self.text = Some(text.to_string());
+    let candidate = text.trim();
+    if candidate.is_empty() || candidate.len() > MAX_URL_LENGTH || self.offered.as_deref() == Some(candidate) {
+      return None;
+    }
+    crate::validate_url_inner(candidate, false).ok()?;
+    self.offered = Some(candidate.to_string());
+    self.offered.clone()
+  }
+}
+
+// Starts or stops the watcher. Starting it again while it runs does nothing.
+pub(crate) fn set_enabled(app: &AppHandle, enabled: bool) {
+  let state = app.state::<AppState>();
+  let mut watcher = state.clipboard_watch.lock_or_recover();
+  match (enabled, watcher.as_ref()) {
+    (true, None) => {
+      let token = CancellationToken::new();
+      tauri::async_runtime::spawn(watch(app.clone(), token.clone()));
+      *watcher = Some(token);
+    }
+    (false, Some(token)) => {
+      token.cancel();
+      *watcher = None;
+    }
+    _ => {}
+  }
+}
+
+async fn watch(app: AppHandle, cancel: CancellationToken) {
+  // Whatever was copied before the watcher started isn't offered
+  let mut seen = Seen { text: read(&app), offered: None };
+  loop {
+    tokio::select! {
+      _ = cancel.cancelled() => return,
+      _ = tokio::time::sleep(POLL_INTERVAL) => {}
+    }
+    let Some(text) = read(&app) else { continue };
+    let Some(url) = seen.next(&text) else { continue };
+    let allowed = url::Url::parse(&url)
+      .is_ok_and(|parsed| crate::check_host_rules(&app.state::<AppState>(), &parsed).is_ok());
+    if !allowed || cancel.is_cancelled() {
+      continue;
+    }
+    if let Err(e) = app.emit("download://clipboard-url", ClipboardUrl { url: &url }) {
+      log::warn!("Failed to emit clipboard URL: {}", e);
+    }
+  }
+}
+
+// The clipboard's text; None when it is empty or holds something else
+fn read(app: &AppHandle) -> Option<String> {
+  app.clipboard().read_text().ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn links_are_offered_once() {
+    let mut seen = Seen::default();
+    assert_eq!(seen.next("https://example.com/a.zip\n").as_deref(), Some("https://example.com/a.zip"));
+    assert_eq!(seen.next("https://example.com/a.zip\n"), None);
+    // Copying the same link again after something else isn't offered either
+    assert_eq!(seen.next("some words"), None);
+    assert_eq!(seen.next("https://example.com/a.zip"), None);
+    assert_eq!(seen.next("http://127.0.0.1/admin"), None);
+    assert_eq!(seen.next("ftp://example.com/b.zip"), None);
+    assert_eq!(seen.next("https://example.com/b.zip").as_deref(), Some("https://example.com/b.zip"));
+  }
+}
@@ -0,0 +1,217 @@
+// Converting completed downloads with ffmpeg, started by convert_download or
+// by the post_process option once a download completes. Only the fixed
+// operations below exist; callers pick one by name and never pass ffmpeg
+// arguments of their own. The derived file is written next to the download,
+// which itself is left as it is.
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::{AppHandle, AppState};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Conversion {
+  // Copies the streams into another container without re-encoding
+  RemuxMp4,
+  RemuxMkv,
+  // Drops the video and encodes the audio track
+  ExtractAudioMp3,
+  ExtractAudioM4a,
+}
+
+impl Conversion {
+  fn extension(self) -> &'static str {
+    match self {
+      Self::RemuxMp4 => "mp4",
+      Self::RemuxMkv => "mkv",
+      Self::ExtractAudioMp3 => "mp3",
+      Self::ExtractAudioM4a => "m4a",
+    }
+  }
+
+  // Output options, placed between the input and the output file
+  fn args(self) -> &'static [&'static str] {
+    match self {
+      Self::RemuxMp4 => &["-map", "0:v?", "-map", "0:a?", "-c", "copy", "-movflags", "+faststart"],
+      Self::RemuxMkv => &["-map", "0", "-c", "copy"],
+      Self::ExtractAudioMp3 => &["-vn", "-c:a", "libmp3lame", "-q:a", "2"],
+      Self::ExtractAudioM4a => &["-vn", "-c:a", "aac", "-b:a", "192k"],
+    }
+  }
+}
+
+// The latest conversion of a download
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct ConversionState {
+  pub operation: Conversion,
+  // "converting", "completed" or "error"
+  pub status: String,
+  // Percent done; None while the duration of the input is unknown
+  pub progress: Option<f64>,
+  // The derived file
+  pub path: PathBuf,
+  pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct ConversionEvent<'a> {
+  id: &'a str,
+  #[serde(flatten)]
+  conversion: &'a ConversionState,
+}
+
+// Checks that the download can be converted, records the conversion as
+// "converting" and runs it in the background. Fails for downloads that
+// aren't completed media files, ones already converting, and when ffmpeg
+// isn't installed.
+pub(crate) async fn start(app: &AppHandle, download_id: &str, operation: Conversion) -> Result<(), String> {
+  let state = app.state::<AppState>();
+  let source = crate::completed_file(&state, download_id).await.map_err(|e| e.to_string())?;
+  if !crate::downloader::ffmpeg_available().await {
+    return Err("ffmpeg is not installed; install it to convert downloads".to_string());
+  }
+  let conversion = {
+    let mut downloads = state.downloads.lock().await;
+    let Some(download) = downloads.get(download_id) else {
+      return Err(format!("Download {} was removed", download_id));
+    };
+    if !crate::media::is_media(&download.filename) {
+      return Err(format!("{} is not a video or audio file", download.filename));
+    }
+    if download.conversion.as_ref().is_some_and(|conversion| conversion.status == "converting") {
+      return Err(format!("Download {} is already being converted", download_id));
+    }
+    let stem = Path::new(&download.filename)
+      .file_stem()
+      .map_or_else(|| download.filename.clone(), |stem| stem.to_string_lossy().into_owned());
+    let target = source.with_file_name(format!("{}.{}", stem, operation.extension()));
+    let (_, path) = crate::reserve_target(&downloads, &target, download_id);
+    let conversion = ConversionState { operation, status: "converting".to_string(), progress: None, path, error: None };
+    if let Some(download) = downloads.get_mut(download_id) {
+      download.conversion = Some(conversion.clone());
+    }
+    conversion
+  };
+  emit(app, download_id, &conversion);
+  log::info!("Converting {} with {:?} to {}", download_id, operation, conversion.path.display());
+  tauri::async_runtime::spawn(run(app.clone(), download_id.to_string(), source, conversion));
+  Ok(())
+}
+
+async fn run(app: AppHandle, download_id: String, source: PathBuf, mut conversion: ConversionState) {
+  let (progress_app, progress_id, progress_state) = (app.clone(), download_id.clone(), conversion.clone());
+  let target = conversion.path.clone();
+  let result = tauri::async_runtime::spawn_blocking(move || {
+    let mut last = None;
+    transcode(&source, &target, progress_state.operation, |percent| {
+      // One event per whole percent is plenty for a progress bar
+      if last != Some(percent.floor()) {
+        last = Some(percent.floor());
+        let update = ConversionState { progress: Some(percent), ..progress_state.clone() };
+        emit(&progress_app, &progress_id, &update);
+      }
+    })
+  })
+  .await
+  .map_err(|e| e.to_string())
+  .and_then(|result| result);
+
+  match result {
+    Ok(()) => {
+      log::info!("Converted {} to {}", download_id, conversion.path.display());
+      conversion.status = "completed".to_string();
+      conversion.progress = Some(100.0);
+    }
+    Err(e) => {
+      log::warn!("Converting {} failed: {}", download_id, e);
+      let _ = std::fs::remove_file(&conversion.path);
+      conversion.status = "error".to_string();
+      conversion.error = Some(e);
+    }
+  }
+  let state = app.state::<AppState>();
+  if let Some(download) = state.downloads.lock().await.get_mut(&download_id) {
+    download.conversion = Some(conversion.clone());
+  }
+  crate::persistence::save(&state).await;
+  emit(&app, &download_id, &conversion);
+}
+
+// Runs ffmpeg, reporting the percentage done when the input's duration is
+// known. Never overwrites an existing file.
+fn transcode(source: &Path, target: &Path, operation: Conversion, mut progress: impl FnMut(f64)) -> Result<(), String> {
+  let duration = crate::media::probe(source)
+    .ok()
+    .flatten()
+    .and_then(|info| info.duration_secs)
+    .filter(|duration| *duration > 0.0);
+  let mut child = Command::new("ffmpeg")
+    .args(["-hide_banner", "-nostdin", "-n", "-loglevel", "error", "-nostats", "-progress", "pipe:1", "-i"])
+    .arg(source)
+    .args(operation.args())
+    .arg(target)
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| match e.kind() {
+      std::io::ErrorKind::NotFound => "ffmpeg is not installed; install it to convert downloads".to_string(),
+      _ => format!("Failed to run ffmpeg: {}", e),
+    })?;
+
+  // Drained on its own thread so a chatty stderr can't block ffmpeg
+  let stderr = child.stderr.take().map(|mut stderr| {
+    std::thread::spawn(move || {
+      let mut text = String::new();
+      let _ = stderr.read_to_string(&mut text);
+      text
+    })
+  });
+  if let Some(stdout) = child.stdout.take() {
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+      if let (Some(seconds), Some(duration)) = (progress_seconds(&line), duration) {
+        progress((seconds / duration * 100.0).clamp(0.0, 100.0));
+      }
+    }
+  }
+  let status = child.wait().map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+  let stderr = stderr.and_then(|reader| reader.join().ok()).unwrap_or_default();
+  if status.success() {
+    Ok(())
+  } else if stderr.trim().is_empty() {
+    Err(format!("ffmpeg failed ({})", status))
+  } else {
+    Err(format!("ffmpeg failed: {}", stderr.trim()))
+  }
+}
+
+// Seconds of output written so far, from an "out_time_us=..." line of
+// ffmpeg's -progress output
+pub(crate) fn progress_seconds(line: &str) -> Option<f64> {
+  let micros: u64 = line.strip_prefix("out_time_us=")?.trim().parse().ok()?;
+  Some(micros as f64 / 1_000_000.0)
+}
+
+fn emit(app: &AppHandle, download_id: &str, conversion: &ConversionState) {
+  if let Err(e) = app.emit("download://conversion", ConversionEvent { id: download_id, conversion }) {
+    log::warn!("Failed to emit conversion progress for {}: {}", download_id, e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_progress_lines() {
+    assert_eq!(progress_seconds("out_time_us=2500000"), Some(2.5));
+    assert_eq!(progress_seconds("out_time_us=N/A"), None);
+    assert_eq!(progress_seconds("frame=120"), None);
+    let operation: Conversion = serde_json::from_str("\"extract_audio_mp3\"").unwrap();
+    assert_eq!(operation.extension(), "mp3");
+    assert!(serde_json::from_str::<Conversion>("\"-i /etc/passwd\"").is_err());
+  }
+}
@@ -0,0 +1,154 @@
+// Session cookies for authenticated downloads, e.g. from the crawler backend
+// login, set with set_cookies. Cookies set for a domain are sent to it and its
+// subdomains, and only while a request stays on the registrable domain it
+// started on, so a redirect to another site never carries them along.
+use std::collections::HashMap;
+
+// Second-level labels that are registered under, like "co" in "example.co.uk".
+// Without a public suffix list this covers the common country-code layouts.
+const SHARED_SECOND_LEVEL: &[&str] = &["ac", "co", "com", "edu", "gov", "net", "org", "ne", "or"];
+
+#[derive(Default)]
+pub(crate) struct CookieJar {
+  // name=value pairs by the normalized domain they were set for
+  cookies: HashMap<String, Vec<(String, String)>>,
+}
+
+impl CookieJar {
+  // Replaces the cookies of `domain`; an empty list removes them. Each entry
+  // is "name=value" and may carry Set-Cookie attributes, which are ignored.
+  // Returns how many cookies the domain has now.
+  pub(crate) fn set(&mut self, domain: &str, cookies: &[String]) -> Result<usize, String> {
+    let domain = normalize(domain);
+    let is_ip = domain.parse::<std::net::IpAddr>().is_ok();
+    if !is_ip && domain.split('.').count() < registered_labels(&domain) {
+      return Err(format!("Cookies can't be set for {:?}, which is not a registrable domain", domain));
+    }
+    let mut parsed: Vec<(String, String)> = Vec::new();
+    for cookie in cookies {
+      let (name, value) = parse(cookie)?;
+      parsed.retain(|(existing, _)| *existing != name);
+      parsed.push((name, value));
+    }
+    let count = parsed.len();
+    if parsed.is_empty() {
+      self.cookies.remove(&domain);
+    } else {
+      self.cookies.insert(domain, parsed);
+    }
+    Ok(count)
+  }
+
+  // Cookie header for a request to `host`, with the cookies of the most
+  // specific matching domain winning on a name clash
+  pub(crate) fn header(&self, host: &str) -> Option<String> {
+    let host = normalize(host);
+    let mut domains: Vec<&String> = self
+      .cookies
+      .keys()
+      .filter(|domain| host == **domain || host.ends_with(&format!(".{}", domain)))
+      .collect();
+    domains.sort_by_key(|domain| std::cmp::Reverse(domain.len()));
+    let mut pairs: Vec<&(String, String)> = Vec::new();
+    for domain in domains {
+      for pair in &self.cookies[domain] {
+        if !pairs.iter().any(|(name, _)| *name == pair.0) {
+          pairs.push(pair);
+        }
+      }
+    }
+    if pairs.is_empty() {
+      return None;
+    }
+    let header: Vec<String> = pairs.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    Some(header.join("; "))
+  }
+}
+
+fn normalize(host: &str) -> String {
+  host
+    .trim()
+    .trim_start_matches('.')
+    .trim_start_matches('[')
+    .trim_end_matches(']')
+    .trim_end_matches('.')
+    .to_lowercase()
+}
+
+fn parse(cookie: &str) -> Result<(String, String), String> {
+  let pair = cookie.split(';').next().unwrap_or_default();
+  let (name, value) = pair
+    .split_once('=')
+    .ok_or_else(|| format!("Cookie {:?} is not name=value", cookie))?;
+  let (name, value) = (name.trim(), value.trim());
+  let invalid = |c: char| c.is_control() || c == ';' || c == ',';
+  if name.is_empty() || name.contains(|c: char| invalid(c) || c == '=' || c.is_whitespace()) {
+    return Err(format!("Invalid cookie name {:?}", name));
+  }
+  if value.contains(invalid) {
+    return Err(format!("Invalid value for cookie {}", name));
+  }
+  Ok((name.to_string(), value.to_string()))
+}
+
+// The part of the host a site registers, e.g. "example.com" for
+// "cdn.example.com" and "example.co.uk" for "www.example.co.uk". IP addresses
+// are their own registrable domain.
+pub(crate) fn registrable_domain(host: &str) -> String {
+  let host = normalize(host);
+  if host.parse::<std::net::IpAddr>().is_ok() {
+    return host;
+  }
+  let labels: Vec<&str> = host.split('.').collect();
+  labels[labels.len().saturating_sub(registered_labels(&host))..].join(".")
+}
+
+// How many trailing labels of a normalized host make up its registrable domain
+fn registered_labels(host: &str) -> usize {
+  let labels: Vec<&str> = host.rsplit('.').take(2).collect();
+  match labels[..] {
+    [tld, second] if tld.len() == 2 && SHARED_SECOND_LEVEL.contains(&second) => 3,
+    _ => 2,
+  }
+}
+
+// Whether a redirect from `from` to `to` stays on the same site
+pub(crate) fn same_site(from: &str, to: &str) -> bool {
+  registrable_domain(from) == registrable_domain(to)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cookies_are_scoped_to_their_domain() {
+    let mut jar = CookieJar::default();
+    assert_eq!(jar.set(".Example.com", &["session=abc; Path=/; HttpOnly".to_string()]), Ok(1));
+    jar.set("media.example.com", &["session=media".to_string(), "cdn=1".to_string()]).unwrap();
+    assert_eq!(jar.header("example.com").as_deref(), Some("session=abc"));
+    assert_eq!(jar.header("media.example.com").as_deref(), Some("session=media; cdn=1"));
+    assert_eq!(jar.header("badexample.com"), None);
+    assert_eq!(jar.header("example.org"), None);
+    assert_eq!(jar.set("media.example.com", &[]), Ok(0));
+    assert_eq!(jar.header("media.example.com").as_deref(), Some("session=abc"));
+  }
+
+  #[test]
+  fn rejects_public_suffixes_and_bad_cookies() {
+    let mut jar = CookieJar::default();
+    assert!(jar.set("com", &["a=b".to_string()]).is_err());
+    assert!(jar.set("co.uk", &["a=b".to_string()]).is_err());
+    assert!(jar.set("example.com", &["novalue".to_string()]).is_err());
+    assert!(jar.set("example.com", &["bad\nname=1".to_string()]).is_err());
+  }
+
+  #[test]
+  fn redirects_within_a_site_keep_cookies() {
+    assert!(same_site("www.example.com", "cdn.example.com"));
+    assert!(same_site("a.example.co.uk", "example.co.uk"));
+    assert!(!same_site("a.example.co.uk", "other.co.uk"));
+    assert!(!same_site("example.com", "example.net"));
+    assert!(!same_site("10.0.0.1", "10.0.0.2"));
+  }
+}
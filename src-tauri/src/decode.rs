@@ -0,0 +1,202 @@
+// Decodes gzip, deflate and brotli response bodies on the fly so files are
+// written as the server's resource, not as its transfer encoding
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt, TryStreamExt};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING};
+use tokio::io::AsyncBufReadExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+// Sent with every request that starts at byte zero. Range requests ask for
+// identity so offsets refer to the decoded file already on disk.
+pub(crate) const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+// First bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Encoding {
+  Gzip,
+  Deflate,
+  Brotli,
+}
+
+impl Encoding {
+  pub(crate) fn name(self) -> &'static str {
+    match self {
+      Encoding::Gzip => "gzip",
+      Encoding::Deflate => "deflate",
+      Encoding::Brotli => "br",
+    }
+  }
+}
+
+// Reads Content-Encoding. Identity (or no header) is None; stacked or unknown
+// encodings are rejected rather than written to disk still encoded.
+pub(crate) fn content_encoding(headers: &HeaderMap) -> Result<Option<Encoding>, String> {
+  let Some(value) = headers.get(CONTENT_ENCODING) else {
+    return Ok(None);
+  };
+  let value = value
+    .to_str()
+    .map_err(|_| "Invalid Content-Encoding header".to_string())?
+    .trim()
+    .to_ascii_lowercase();
+  match value.as_str() {
+    "" | "identity" => Ok(None),
+    "gzip" | "x-gzip" => Ok(Some(Encoding::Gzip)),
+    "deflate" => Ok(Some(Encoding::Deflate)),
+    "br" => Ok(Some(Encoding::Brotli)),
+    other => Err(format!("Unsupported Content-Encoding: {}", other)),
+  }
+}
+
+// A response body yielding decoded bytes, plus a count of the bytes that
+// actually came over the wire, which is what Content-Length describes
+pub(crate) struct Body {
+  pub stream: BoxStream<'static, std::io::Result<bytes::Bytes>>,
+  wire_bytes: Arc<AtomicU64>,
+}
+
+type RawStream = BoxStream<'static, std::io::Result<bytes::Bytes>>;
+
+// A body that can't be what was asked for, as opposed to one that broke off;
+// retrying would only get the same bytes again
+#[derive(Debug)]
+pub(crate) struct Malformed(pub String);
+
+impl std::fmt::Display for Malformed {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl std::error::Error for Malformed {}
+
+fn malformed(message: String) -> std::io::Error {
+  std::io::Error::other(Malformed(message))
+}
+
+// The body as it comes over the wire, counted into `wire_bytes`
+fn raw(response: reqwest::Response, wire_bytes: &Arc<AtomicU64>) -> RawStream {
+  let counter = wire_bytes.clone();
+  // Network errors stay wrapped so callers can still classify them
+  response
+    .bytes_stream()
+    .inspect_ok(move |chunk| {
+      counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    })
+    .map_err(std::io::Error::other)
+    .boxed()
+}
+
+impl Body {
+  pub(crate) fn new(response: reqwest::Response) -> Result<Self, String> {
+    let encoding = content_encoding(response.headers())?;
+    let wire_bytes = Arc::new(AtomicU64::new(0));
+    let raw = raw(response, &wire_bytes);
+    let stream = match encoding {
+      None => raw.boxed(),
+      Some(Encoding::Gzip) => ReaderStream::new(GzipDecoder::new(StreamReader::new(raw))).boxed(),
+      Some(Encoding::Deflate) => ReaderStream::new(ZlibDecoder::new(StreamReader::new(raw))).boxed(),
+      Some(Encoding::Brotli) => ReaderStream::new(BrotliDecoder::new(StreamReader::new(raw))).boxed(),
+    };
+    Ok(Self { stream, wire_bytes })
+  }
+
+  // Like new, but also decodes a body that starts with the gzip magic bytes
+  // when Content-Encoding doesn't announce one. Some HLS servers store their
+  // segments gzipped and send them as plain octet streams.
+  pub(crate) fn sniffing_gzip(response: reqwest::Response) -> Result<Self, String> {
+    if content_encoding(response.headers())?.is_some() {
+      return Self::new(response);
+    }
+    let wire_bytes = Arc::new(AtomicU64::new(0));
+    let mut reader = StreamReader::new(raw(response, &wire_bytes));
+    let stream = futures_util::stream::once(async move {
+      match reader.fill_buf().await.map(|start| start.starts_with(&GZIP_MAGIC)) {
+        Ok(true) => ReaderStream::new(GzipDecoder::new(reader)).boxed(),
+        Ok(false) => ReaderStream::new(reader).boxed(),
+        Err(e) => futures_util::stream::once(async move { Err(e) }).boxed(),
+      }
+    })
+    .flatten()
+    .boxed();
+    Ok(Self { stream, wire_bytes })
+  }
+
+  // The bytes first..=last of a file of `total` bytes from a response some
+  // servers send as multipart/byteranges even for a single range. The parts
+  // are joined back into one stream, which only works when they follow each
+  // other without gaps or overlaps from `first` on and stay within `last`;
+  // anything else fails with Malformed before it reaches the file.
+  pub(crate) fn byteranges(response: reqwest::Response, boundary: &str, first: u64, last: u64, total: u64) -> Self {
+    let wire_bytes = Arc::new(AtomicU64::new(0));
+    let parser = crate::segments::ByteRanges::new(boundary, total);
+    let state = Some((raw(response, &wire_bytes), parser, first));
+    let stream = futures_util::stream::unfold(state, move |state| async move {
+      let (mut raw, mut parser, mut next) = state?;
+      loop {
+        let chunk = match raw.next().await {
+          Some(Ok(chunk)) => chunk,
+          Some(Err(e)) => return Some((Err(e), None)),
+          None => return parser.finish().err().map(|e| (Err(malformed(e)), None)),
+        };
+        let parts = match parser.feed(&chunk) {
+          Ok(parts) => parts,
+          Err(e) => return Some((Err(malformed(e)), None)),
+        };
+        let mut joined = Vec::new();
+        for (offset, data) in parts {
+          let end = offset + data.len() as u64;
+          let problem = if offset != next {
+            Some(format!("multipart/byteranges part at byte {} doesn't continue from byte {}", offset, next))
+          } else if end > last + 1 {
+            Some(format!("multipart/byteranges part runs past the requested byte {}", last))
+          } else {
+            None
+          };
+          if let Some(problem) = problem {
+            return Some((Err(malformed(problem)), None));
+          }
+          next = end;
+          joined.extend(data);
+        }
+        if !joined.is_empty() {
+          return Some((Ok(bytes::Bytes::from(joined)), Some((raw, parser, next))));
+        }
+      }
+    })
+    .boxed();
+    Self { stream, wire_bytes }
+  }
+
+  pub(crate) fn wire_bytes(&self) -> u64 {
+    self.wire_bytes.load(Ordering::Relaxed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::header::HeaderValue;
+
+  fn encoding_of(value: &str) -> Result<Option<Encoding>, String> {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_str(value).unwrap());
+    content_encoding(&headers)
+  }
+
+  #[test]
+  fn recognizes_supported_encodings() {
+    assert_eq!(content_encoding(&HeaderMap::new()), Ok(None));
+    assert_eq!(encoding_of("identity"), Ok(None));
+    assert_eq!(encoding_of("GZIP"), Ok(Some(Encoding::Gzip)));
+    assert_eq!(encoding_of("deflate"), Ok(Some(Encoding::Deflate)));
+    assert_eq!(encoding_of("br"), Ok(Some(Encoding::Brotli)));
+    assert!(encoding_of("zstd").is_err());
+    assert!(encoding_of("gzip, br").is_err());
+  }
+}
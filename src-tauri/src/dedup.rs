@@ -0,0 +1,129 @@
+// Optional content dedup, turned on with set_dedup_content. A completed file
+// whose SHA-256 matches another completed download is replaced by a hardlink
+// to that download's file, so identical content fetched from different URLs
+// takes disk space once. Where hardlinks aren't possible (another filesystem,
+// FAT, some network shares) both copies are kept.
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{AppState, DownloadInfo};
+
+// The download a completed file turned out to duplicate
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Duplicate {
+  pub id: String,
+  pub path: PathBuf,
+  // Whether the file is now a hardlink to `path`; false when linking failed
+  // and the two copies stay independent
+  pub linked: bool,
+}
+
+pub(crate) fn enabled(state: &AppState) -> bool {
+  state.dedup_content.load(Ordering::Relaxed)
+}
+
+// Other completed downloads recorded with the same hash, oldest first
+fn candidates(downloads: &HashMap<String, DownloadInfo>, download_id: &str, sha256: &str) -> Vec<(String, PathBuf)> {
+  let mut found: Vec<&DownloadInfo> = downloads
+    .values()
+    .filter(|download| download.id != download_id && download.status == "completed")
+    .filter(|download| download.sha256.as_deref() == Some(sha256))
+    .collect();
+  found.sort_by_key(|download| download.created_at);
+  found.into_iter().map(|download| (download.id.clone(), download.path.clone())).collect()
+}
+
+// Whether `original` still holds the content the hash was recorded for. The
+// file may have been edited since it completed, so it is hashed again rather
+// than trusted.
+fn same_content(original: &Path, duplicate: &Path, sha256: &str) -> bool {
+  let (Ok(original_meta), Ok(duplicate_meta)) = (std::fs::metadata(original), std::fs::metadata(duplicate)) else {
+    return false;
+  };
+  if !original_meta.is_file() || original_meta.len() != duplicate_meta.len() {
+    return false;
+  }
+  let mut hasher = Sha256::new();
+  let hashed = std::fs::File::open(original).and_then(|mut file| std::io::copy(&mut file, &mut hasher));
+  hashed.is_ok() && hex::encode(hasher.finalize()) == sha256
+}
+
+// Replaces `duplicate` with a hardlink to `original`. The link is made under a
+// temporary name and renamed over the duplicate, so a failure at any point
+// leaves the duplicate as it was.
+fn link(original: &Path, duplicate: &Path) -> std::io::Result<()> {
+  let mut name = duplicate.file_name().unwrap_or_default().to_os_string();
+  name.push(".dedup");
+  let temp = duplicate.with_file_name(name);
+  match std::fs::remove_file(&temp) {
+    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+    _ => {}
+  }
+  std::fs::hard_link(original, &temp)?;
+  std::fs::rename(&temp, duplicate).inspect_err(|_| {
+    let _ = std::fs::remove_file(&temp);
+  })
+}
+
+// Looks for an earlier download with the same content as the one that just
+// completed at `path` and links the two. Returns what was found, if anything.
+pub(crate) async fn deduplicate(state: &AppState, download_id: &str, path: &Path, sha256: &str) -> Option<Duplicate> {
+  let candidates = candidates(&*state.downloads.lock().await, download_id, sha256);
+  if candidates.is_empty() {
+    return None;
+  }
+  let (path, sha256) = (path.to_path_buf(), sha256.to_string());
+  let download_id = download_id.to_string();
+  tauri::async_runtime::spawn_blocking(move || {
+    let (id, original) = candidates
+      .into_iter()
+      .find(|(_, original)| *original != path && same_content(original, &path, &sha256))?;
+    let linked = match link(&original, &path) {
+      Ok(()) => {
+        log::info!("Download {} is a duplicate of {}; linked to {}", download_id, id, original.display());
+        true
+      }
+      Err(e) => {
+        log::info!("Download {} duplicates {} but can't be linked, keeping both: {}", download_id, id, e);
+        false
+      }
+    };
+    Some(Duplicate { id, path: original, linked })
+  })
+  .await
+  .ok()
+  .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn duplicates_become_links_to_the_original() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-dedup-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let original = dir.join("a.bin");
+    let duplicate = dir.join("b.bin");
+    std::fs::write(&original, b"same bytes").unwrap();
+    std::fs::write(&duplicate, b"same bytes").unwrap();
+    let sha256 = hex::encode(Sha256::digest(b"same bytes"));
+
+    assert!(same_content(&original, &duplicate, &sha256));
+    assert!(!same_content(&original, &duplicate, &"00".repeat(32)));
+    link(&original, &duplicate).unwrap();
+    assert_eq!(std::fs::read(&duplicate).unwrap(), b"same bytes");
+    assert!(!dir.join("b.bin.dedup").exists());
+
+    // A missing original leaves the duplicate untouched
+    std::fs::remove_file(&original).unwrap();
+    assert!(link(&original, &duplicate).is_err());
+    assert_eq!(std::fs::read(&duplicate).unwrap(), b"same bytes");
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
@@ -0,0 +1,104 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+// Error returned by every command. It reaches the frontend as
+// `{ kind, message }` so the UI can branch on `kind` while `message` stays
+// readable for people and logs.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub(crate) enum DownloadError {
+  #[error("{0}")]
+  InvalidUrl(String),
+  #[error("{0}")]
+  BlockedHost(String),
+  #[error("{0}")]
+  Network(String),
+  #[error("{0}")]
+  DiskFull(String),
+  #[error("Download not found: {0}")]
+  NotFound(String),
+  #[error("{0}")]
+  Io(String),
+  // The app isn't allowed to write where it needs to; the message names the
+  // path so the user knows which folder to fix
+  #[error("{0}")]
+  PermissionDenied(String),
+  // A command argument was out of range or malformed
+  #[error("{0}")]
+  InvalidInput(String),
+  // The request doesn't fit the download's current status
+  #[error("{0}")]
+  InvalidState(String),
+  #[error("{0}")]
+  Internal(String),
+}
+
+impl DownloadError {
+  pub(crate) fn kind(&self) -> &'static str {
+    match self {
+      Self::InvalidUrl(_) => "invalid_url",
+      Self::BlockedHost(_) => "blocked_host",
+      Self::Network(_) => "network",
+      Self::DiskFull(_) => "disk_full",
+      Self::NotFound(_) => "not_found",
+      Self::Io(_) => "io",
+      Self::PermissionDenied(_) => "permission_denied",
+      Self::InvalidInput(_) => "invalid_input",
+      Self::InvalidState(_) => "invalid_state",
+      Self::Internal(_) => "internal",
+    }
+  }
+
+  // Wraps an I/O failure, telling a full disk apart from other errors
+  pub(crate) fn io(context: &str, e: std::io::Error) -> Self {
+    let message = format!("{}: {}", context, e);
+    if is_disk_full(&e) {
+      Self::DiskFull(message)
+    } else {
+      Self::Io(message)
+    }
+  }
+
+  // io for a failure on `path`, reporting a refused access as
+  // PermissionDenied with the path in place of the OS message
+  pub(crate) fn file(context: &str, path: &std::path::Path, e: std::io::Error) -> Self {
+    if e.kind() == std::io::ErrorKind::PermissionDenied {
+      Self::PermissionDenied(format!("permission denied: {}", path.display()))
+    } else {
+      Self::io(context, e)
+    }
+  }
+}
+
+// ErrorKind::StorageFull is newer than our MSRV, so check the OS codes
+pub(crate) fn is_disk_full(e: &std::io::Error) -> bool {
+  #[cfg(unix)]
+  const DISK_FULL_CODES: &[i32] = &[libc::ENOSPC];
+  // ERROR_HANDLE_DISK_FULL and ERROR_DISK_FULL
+  #[cfg(windows)]
+  const DISK_FULL_CODES: &[i32] = &[39, 112];
+  #[cfg(not(any(unix, windows)))]
+  const DISK_FULL_CODES: &[i32] = &[];
+
+  e.raw_os_error().is_some_and(|code| DISK_FULL_CODES.contains(&code))
+}
+
+// Lock failures and other unexpected conditions
+impl From<String> for DownloadError {
+  fn from(message: String) -> Self {
+    Self::Internal(message)
+  }
+}
+
+impl From<&str> for DownloadError {
+  fn from(message: &str) -> Self {
+    Self::Internal(message.to_string())
+  }
+}
+
+impl Serialize for DownloadError {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut error = serializer.serialize_struct("DownloadError", 2)?;
+    error.serialize_field("kind", self.kind())?;
+    error.serialize_field("message", &self.to_string())?;
+    error.end()
+  }
+}
@@ -0,0 +1,548 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use percent_encoding::percent_decode_str;
+
+// Longest file name we produce, in bytes; most filesystems cap names at 255
+pub(crate) const MAX_FILENAME_BYTES: usize = 200;
+// Longest suffix of a file fetched alongside a download
+const MAX_SUFFIX_BYTES: usize = 32;
+// Most folders a download's subdirectory may nest
+pub(crate) const MAX_SUBDIR_DEPTH: usize = 8;
+
+// Device names Windows refuses as file names, with or without an extension
+const RESERVED_WINDOWS_NAMES: &[&str] = &[
+  "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+  "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Turns an untrusted name into one that is safe on every platform: directory
+// components are flattened ("a/b/c.txt" becomes "a_b_c.txt") with "." and ".."
+// dropped, characters illegal on Windows or control characters become '_',
+// reserved device names are prefixed, and the length is clamped while keeping
+// the extension. The name is normalized to NFC first, so "é" sent decomposed
+// as "e" plus an accent is written as the single character other programs
+// look for.
+pub(crate) fn sanitize(name: &str) -> Result<String, String> {
+  let composed = icu_normalizer::ComposingNormalizerBorrowed::new_nfc().normalize(name);
+  let joined = composed
+    .split(['/', '\\'])
+    .map(str::trim)
+    .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+    .collect::<Vec<_>>()
+    .join("_");
+
+  let replaced: String = joined
+    .chars()
+    .map(|c| match c {
+      '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .collect();
+  // Windows silently drops trailing dots and spaces
+  let mut sanitized = replaced.trim_end_matches(['.', ' ']).trim_start().to_string();
+  if sanitized.is_empty() {
+    return Err(format!("Invalid filename: {:?}", name));
+  }
+
+  let stem = sanitized.split('.').next().unwrap_or_default();
+  if RESERVED_WINDOWS_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+    sanitized.insert(0, '_');
+  }
+  Ok(clamp_length(&sanitized))
+}
+
+// Shortens the stem so the whole name fits MAX_FILENAME_BYTES, cutting on a
+// character boundary and keeping a short extension intact.
+fn clamp_length(name: &str) -> String {
+  if name.len() <= MAX_FILENAME_BYTES {
+    return name.to_string();
+  }
+  let (stem, extension) = match name.rsplit_once('.') {
+    Some((stem, extension)) if !stem.is_empty() && extension.len() <= 16 => (stem, Some(extension)),
+    _ => (name, None),
+  };
+  let budget = MAX_FILENAME_BYTES - extension.map_or(0, |extension| extension.len() + 1);
+  let mut end = budget.min(stem.len());
+  while !stem.is_char_boundary(end) {
+    end -= 1;
+  }
+  match extension {
+    Some(extension) => format!("{}.{}", &stem[..end], extension),
+    None => stem[..end].to_string(),
+  }
+}
+
+// Builds the n-th collision variant of a name: "clip.mp4" becomes "clip (n).mp4".
+pub(crate) fn numbered(name: &str, n: u32) -> String {
+  if n == 0 {
+    return name.to_string();
+  }
+  match name.rsplit_once('.') {
+    Some((stem, extension)) if !stem.is_empty() => format!("{} ({}).{}", stem, n, extension),
+    _ => format!("{} ({})", name, n),
+  }
+}
+
+// Checks the suffix of a file fetched alongside a download, like ".srt" or
+// ".en.vtt": a dot followed by characters that survive sanitizing unchanged
+pub(crate) fn validate_suffix(suffix: &str) -> Result<String, String> {
+  let suffix = suffix.trim();
+  let rest = suffix
+    .strip_prefix('.')
+    .ok_or_else(|| format!("Suffix {:?} must start with a dot", suffix))?;
+  if suffix.len() > MAX_SUFFIX_BYTES || sanitize(rest).ok().as_deref() != Some(rest) {
+    return Err(format!("Invalid suffix {:?}", suffix));
+  }
+  Ok(suffix.to_string())
+}
+
+// Name of a file that accompanies `main`: its stem plus the suffix, so
+// "movie.mp4" with ".srt" becomes "movie.srt"
+pub(crate) fn with_suffix(main: &str, suffix: &str) -> Result<String, String> {
+  let stem = match main.rsplit_once('.') {
+    Some((stem, _)) if !stem.is_empty() => stem,
+    _ => main,
+  };
+  sanitize(&format!("{}{}", stem, suffix))
+}
+
+// Joins a sanitized name onto the downloads directory and verifies the result
+// really resolves inside it, even if the directory or file is a symlink.
+pub(crate) fn confined_path(download_dir: &Path, filename: &str) -> Result<PathBuf, String> {
+  std::fs::create_dir_all(download_dir)
+    .map_err(|e| format!("Failed to create downloads directory: {}", e))?;
+  let root = download_dir
+    .canonicalize()
+    .map_err(|e| format!("Failed to resolve downloads directory: {}", e))?;
+  let path = root.join(filename);
+
+  let resolved = match path.canonicalize() {
+    Ok(resolved) => resolved,
+    Err(_) => path.clone(),
+  };
+  if resolved.parent() != Some(root.as_path()) {
+    return Err(format!("Refusing to write outside the downloads directory: {}", filename));
+  }
+  Ok(path)
+}
+
+// Checks the folder inside the downloads directory a download is queued into,
+// like "Movies" or "Music/Live". Every folder name is sanitized like a file
+// name; absolute paths and ".." are refused instead of flattened, as they can
+// only be meant to leave the directory. Returns the path with '/' separators,
+// or None when it names the downloads directory itself.
+pub(crate) fn sanitize_subdir(subdir: &str) -> Result<Option<String>, String> {
+  let trimmed = subdir.trim();
+  let bytes = trimmed.as_bytes();
+  let drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+  if trimmed.starts_with(['/', '\\']) || drive {
+    return Err(format!("Subdirectory {:?} must be relative to the downloads directory", subdir));
+  }
+  let mut parts = Vec::new();
+  for part in trimmed.split(['/', '\\']).map(str::trim) {
+    match part {
+      "" | "." => {}
+      ".." => return Err(format!("Subdirectory {:?} must stay inside the downloads directory", subdir)),
+      part => parts.push(sanitize(part)?),
+    }
+  }
+  if parts.len() > MAX_SUBDIR_DEPTH {
+    return Err(format!("Subdirectory {:?} is nested more than {} levels deep", subdir, MAX_SUBDIR_DEPTH));
+  }
+  Ok((!parts.is_empty()).then(|| parts.join("/")))
+}
+
+// Creates a subdirectory checked by sanitize_subdir one folder at a time,
+// refusing any folder along the way that is a symlink, so the result can't
+// resolve outside the downloads directory. Returns the created directory.
+pub(crate) fn confined_dir(download_dir: &Path, subdir: &str) -> Result<PathBuf, String> {
+  std::fs::create_dir_all(download_dir)
+    .map_err(|e| format!("Failed to create downloads directory: {}", e))?;
+  let mut dir = download_dir
+    .canonicalize()
+    .map_err(|e| format!("Failed to resolve downloads directory: {}", e))?;
+  for part in subdir.split('/') {
+    dir.push(part);
+    match std::fs::symlink_metadata(&dir) {
+      Ok(metadata) if metadata.file_type().is_symlink() => {
+        return Err(format!("Refusing to write through the symlink {}", dir.display()));
+      }
+      Ok(metadata) if metadata.is_dir() => {}
+      Ok(_) => return Err(format!("{} exists and is not a folder", dir.display())),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => match std::fs::create_dir(&dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+          return Err(format!("Failed to create {}: {}", dir.display(), e));
+        }
+        _ => {}
+      },
+      Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    }
+  }
+  Ok(dir)
+}
+
+// Resolves an existing file and verifies it still sits inside the downloads
+// directory, directly or in a subdirectory, before it is handed to the OS.
+pub(crate) fn existing_download(download_dir: &Path, path: &Path) -> Result<PathBuf, String> {
+  let root = download_dir
+    .canonicalize()
+    .map_err(|e| format!("Failed to resolve downloads directory: {}", e))?;
+  let resolved = path
+    .canonicalize()
+    .map_err(|_| format!("File no longer exists: {}", path.display()))?;
+  if !resolved.is_file() {
+    return Err(format!("Not a file: {}", path.display()));
+  }
+  if !resolved.starts_with(&root) {
+    return Err(format!("File is outside the downloads directory: {}", path.display()));
+  }
+  Ok(resolved)
+}
+
+// from_content_disposition for the raw header bytes. Servers put raw UTF-8 in
+// quoted file names although headers are meant to be ASCII; bytes that aren't
+// UTF-8 are read as Windows-1252, as browsers do.
+pub(crate) fn from_disposition_bytes(value: &[u8]) -> Option<String> {
+  from_content_disposition(&header_text(value))
+}
+
+fn header_text(bytes: &[u8]) -> Cow<'_, str> {
+  match std::str::from_utf8(bytes) {
+    Ok(text) => Cow::Borrowed(text),
+    Err(_) => encoding_rs::WINDOWS_1252.decode_without_bom_handling(bytes).0,
+  }
+}
+
+// Extracts the file name from a Content-Disposition header value. The RFC 5987
+// `filename*` form wins over the plain `filename` parameter when both exist.
+pub(crate) fn from_content_disposition(value: &str) -> Option<String> {
+  let mut plain = None;
+  let mut extended = None;
+  for param in split_params(value).iter().skip(1) {
+    let Some((key, raw)) = param.split_once('=') else { continue };
+    match key.trim().to_ascii_lowercase().as_str() {
+      "filename*" => extended = decode_ext_value(raw.trim()),
+      "filename" => plain = Some(unquote(raw.trim())),
+      _ => {}
+    }
+  }
+  extended.or(plain).and_then(|name| sanitize(&name).ok())
+}
+
+// Extensions for the content types worth naming a file after; the first one
+// is used when a name needs one. Generic types like application/octet-stream
+// say nothing about the file and aren't listed.
+const MIME_EXTENSIONS: &[(&str, &[&str])] = &[
+  ("image/jpeg", &["jpg", "jpeg", "jpe", "jfif"]),
+  ("image/png", &["png"]),
+  ("image/gif", &["gif"]),
+  ("image/webp", &["webp"]),
+  ("image/avif", &["avif"]),
+  ("image/svg+xml", &["svg"]),
+  ("image/bmp", &["bmp"]),
+  ("image/tiff", &["tif", "tiff"]),
+  ("image/x-icon", &["ico"]),
+  ("image/vnd.microsoft.icon", &["ico"]),
+  ("video/mp4", &["mp4", "m4v"]),
+  ("video/webm", &["webm"]),
+  ("video/x-matroska", &["mkv"]),
+  ("video/quicktime", &["mov"]),
+  ("video/x-msvideo", &["avi"]),
+  ("video/mp2t", &["ts"]),
+  ("audio/mpeg", &["mp3"]),
+  ("audio/mp4", &["m4a", "mp4"]),
+  ("audio/aac", &["aac"]),
+  ("audio/ogg", &["ogg", "oga", "opus"]),
+  ("audio/opus", &["opus"]),
+  ("audio/flac", &["flac"]),
+  ("audio/wav", &["wav"]),
+  ("audio/x-wav", &["wav"]),
+  ("application/pdf", &["pdf"]),
+  ("application/zip", &["zip"]),
+  ("application/gzip", &["gz", "tgz"]),
+  ("application/x-gzip", &["gz", "tgz"]),
+  ("application/x-tar", &["tar"]),
+  ("application/x-7z-compressed", &["7z"]),
+  ("application/epub+zip", &["epub"]),
+  ("application/x-bittorrent", &["torrent"]),
+  ("application/json", &["json"]),
+  ("text/csv", &["csv"]),
+  ("text/html", &["html", "htm"]),
+];
+
+// The name with its extension fixed to match the Content-Type, or None when
+// it already matches or the type isn't one of MIME_EXTENSIONS. A name
+// without an extension gets one appended; a wrong one ("image.php" served as
+// image/jpeg) is replaced.
+pub(crate) fn correct_extension(name: &str, content_type: &str) -> Option<String> {
+  let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+  let (_, extensions) = MIME_EXTENSIONS.iter().find(|(known, _)| *known == mime)?;
+  // Only a short alphanumeric tail with a letter in it counts as an
+  // extension, so "release.2024" keeps its number
+  let extension = name.rsplit_once('.').filter(|(stem, extension)| {
+    !stem.is_empty()
+      && (1..=5).contains(&extension.len())
+      && extension.chars().all(|c| c.is_ascii_alphanumeric())
+      && extension.chars().any(|c| c.is_ascii_alphabetic())
+  });
+  let stem = match extension {
+    Some((_, extension)) if extensions.contains(&extension.to_ascii_lowercase().as_str()) => return None,
+    Some((stem, _)) => stem,
+    None => name,
+  };
+  sanitize(&format!("{}.{}", stem, extensions[0])).ok()
+}
+
+// Uses the last non-empty path segment of the URL, percent-decoded.
+pub(crate) fn from_url(url: &str) -> Option<String> {
+  let parsed = url::Url::parse(url).ok()?;
+  let segment = parsed.path_segments()?.rfind(|segment| !segment.is_empty())?;
+  sanitize(&percent_decode_str(segment).decode_utf8_lossy()).ok()
+}
+
+// Splits header parameters on ';' while respecting quoted strings.
+fn split_params(value: &str) -> Vec<String> {
+  let mut params = Vec::new();
+  let mut current = String::new();
+  let mut in_quotes = false;
+  let mut escaped = false;
+  for c in value.chars() {
+    match c {
+      _ if escaped => {
+        current.push(c);
+        escaped = false;
+      }
+      '\\' if in_quotes => {
+        current.push(c);
+        escaped = true;
+      }
+      '"' => {
+        current.push(c);
+        in_quotes = !in_quotes;
+      }
+      ';' if !in_quotes => params.push(std::mem::take(&mut current)),
+      _ => current.push(c),
+    }
+  }
+  params.push(current);
+  params
+}
+
+fn unquote(value: &str) -> String {
+  let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+    return value.to_string();
+  };
+  let mut out = String::with_capacity(inner.len());
+  let mut chars = inner.chars();
+  while let Some(c) = chars.next() {
+    if c == '\\' {
+      if let Some(next) = chars.next() {
+        out.push(next);
+      }
+    } else {
+      out.push(c);
+    }
+  }
+  out
+}
+
+// Decodes an RFC 5987 ext-value: charset'language'percent-encoded-bytes. Any
+// charset the WHATWG encoding standard knows is accepted, so legacy ones like
+// Shift_JIS decode too; bytes invalid in it make the value unusable rather
+// than garbled.
+fn decode_ext_value(value: &str) -> Option<String> {
+  let mut parts = value.splitn(3, '\'');
+  let charset = parts.next()?;
+  let _language = parts.next()?;
+  let encoded = parts.next()?;
+  let bytes: Vec<u8> = percent_decode_str(encoded).collect();
+  let encoding = encoding_rs::Encoding::for_label(charset.trim().as_bytes())?;
+  encoding
+    .decode_without_bom_handling_and_without_replacement(&bytes)
+    .map(Cow::into_owned)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_plain_and_quoted_filenames() {
+    assert_eq!(from_content_disposition("attachment; filename=report.pdf").as_deref(), Some("report.pdf"));
+    assert_eq!(
+      from_content_disposition(r#"attachment; filename="my \"best\" clip.mp4""#).as_deref(),
+      Some("my _best_ clip.mp4")
+    );
+    assert_eq!(from_content_disposition("inline"), None);
+  }
+
+  #[test]
+  fn prefers_extended_filename() {
+    let header = "attachment; filename=\"fallback.mp4\"; filename*=UTF-8''na%C3%AFve%20clip.mp4";
+    assert_eq!(from_content_disposition(header).as_deref(), Some("naïve clip.mp4"));
+  }
+
+  #[test]
+  fn decodes_non_ascii_names() {
+    let japanese = "attachment; filename*=UTF-8''%E5%8B%95%E7%94%BB%E3%83%86%E3%82%B9%E3%83%88.mp4";
+    assert_eq!(from_content_disposition(japanese).as_deref(), Some("動画テスト.mp4"));
+    let shift_jis = "attachment; filename*=Shift_JIS''%93%AE%89%E6.mp4";
+    assert_eq!(from_content_disposition(shift_jis).as_deref(), Some("動画.mp4"));
+    assert_eq!(from_content_disposition("attachment; filename*=UTF-8''%FF.mp4"), None);
+    // Raw UTF-8 in the quoted fallback, and Latin-1 bytes that aren't UTF-8
+    let raw = "attachment; filename=\"Café façade.pdf\"";
+    assert_eq!(from_disposition_bytes(raw.as_bytes()).as_deref(), Some("Café façade.pdf"));
+    assert_eq!(from_disposition_bytes(b"attachment; filename=\"Caf\xe9.pdf\"").as_deref(), Some("Café.pdf"));
+    // Decomposed "e" plus combining acute comes out composed
+    let decomposed = "attachment; filename*=UTF-8''Re%CC%81sume%CC%81.pdf";
+    assert_eq!(from_content_disposition(decomposed).as_deref(), Some("R\u{e9}sum\u{e9}.pdf"));
+  }
+
+  #[test]
+  fn non_ascii_names_land_on_disk() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-unicode-{}", std::process::id()));
+    let headers = [
+      "attachment; filename*=UTF-8''%E6%97%A5%E6%9C%AC%E8%AA%9E.txt",
+      "attachment; filename=\"Ame\u{301}lie.txt\"",
+    ];
+    for header in headers {
+      let name = from_content_disposition(header).unwrap();
+      let path = confined_path(&dir, &name).unwrap();
+      std::fs::write(&path, b"x").unwrap();
+      let listed: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+      assert!(listed.contains(&name), "{:?} not in {:?}", name, listed);
+    }
+    assert!(dir.join("日本語.txt").is_file());
+    assert!(dir.join("Am\u{e9}lie.txt").is_file());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn sanitizes_header_names() {
+    assert_eq!(
+      from_content_disposition("attachment; filename=\"../../etc/passwd\"").as_deref(),
+      Some("etc_passwd")
+    );
+  }
+
+  #[test]
+  fn sanitize_removes_traversal() {
+    assert_eq!(sanitize("../../../etc/passwd").as_deref(), Ok("etc_passwd"));
+    assert_eq!(sanitize("..\\..\\boot.ini").as_deref(), Ok("boot.ini"));
+    assert!(sanitize("..").is_err());
+    assert!(sanitize("/").is_err());
+  }
+
+  #[test]
+  fn corrects_extensions_from_content_type() {
+    assert_eq!(correct_extension("image.php", "image/jpeg").as_deref(), Some("image.jpg"));
+    assert_eq!(correct_extension("download", "application/pdf").as_deref(), Some("download.pdf"));
+    assert_eq!(correct_extension("release.2024", "application/zip").as_deref(), Some("release.2024.zip"));
+    assert_eq!(correct_extension("Photo.JPEG", "image/jpeg; charset=binary"), None);
+    assert_eq!(correct_extension("data.bin", "application/octet-stream"), None);
+  }
+
+  #[test]
+  fn sanitize_flattens_directories() {
+    assert_eq!(sanitize("a/b/c.txt").as_deref(), Ok("a_b_c.txt"));
+  }
+
+  #[test]
+  fn sanitize_replaces_illegal_characters() {
+    assert_eq!(sanitize("what?<now>:*.mp4").as_deref(), Ok("what__now___.mp4"));
+    assert_eq!(sanitize("nul\0byte.txt").as_deref(), Ok("nul_byte.txt"));
+    assert_eq!(sanitize("trailing. . ").as_deref(), Ok("trailing"));
+  }
+
+  #[test]
+  fn sanitize_prefixes_reserved_windows_names() {
+    assert_eq!(sanitize("CON").as_deref(), Ok("_CON"));
+    assert_eq!(sanitize("lpt1.txt").as_deref(), Ok("_lpt1.txt"));
+    assert_eq!(sanitize("console.txt").as_deref(), Ok("console.txt"));
+  }
+
+  #[test]
+  fn sanitize_clamps_long_names() {
+    let long = format!("{}.mp4", "é".repeat(300));
+    let sanitized = sanitize(&long).unwrap();
+    assert!(sanitized.len() <= MAX_FILENAME_BYTES);
+    assert!(sanitized.ends_with(".mp4"));
+    assert!(sanitized.starts_with('é'));
+  }
+
+  #[test]
+  fn numbered_inserts_counter_before_extension() {
+    assert_eq!(numbered("clip.mp4", 0), "clip.mp4");
+    assert_eq!(numbered("clip.mp4", 2), "clip (2).mp4");
+    assert_eq!(numbered("README", 1), "README (1)");
+    assert_eq!(numbered(".hidden", 1), ".hidden (1)");
+  }
+
+  #[test]
+  fn confined_path_stays_in_directory() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-confined-{}", std::process::id()));
+    let path = confined_path(&dir, "video.mp4").unwrap();
+    assert_eq!(path.parent(), Some(dir.canonicalize().unwrap().as_path()));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn existing_download_rejects_missing_and_outside_files() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-existing-{}", std::process::id()));
+    let inner = dir.join("downloads");
+    std::fs::create_dir_all(&inner).unwrap();
+    std::fs::write(inner.join("clip.mp4"), b"x").unwrap();
+    std::fs::write(dir.join("outside.mp4"), b"x").unwrap();
+
+    assert!(existing_download(&inner, &inner.join("clip.mp4")).is_ok());
+    assert!(existing_download(&inner, &inner.join("gone.mp4")).unwrap_err().contains("no longer exists"));
+    assert!(existing_download(&inner, &inner.join("../outside.mp4")).unwrap_err().contains("outside"));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn subdirectories_stay_inside_the_downloads_directory() {
+    assert_eq!(sanitize_subdir(" Movies/ ").unwrap().as_deref(), Some("Movies"));
+    assert_eq!(sanitize_subdir("Music\\./Live: 2024").unwrap().as_deref(), Some("Music/Live_ 2024"));
+    assert_eq!(sanitize_subdir("./").unwrap(), None);
+    for refused in ["/etc", "\\\\server\\share", "C:\\Users", "c:relative", "Movies/../..", ".."] {
+      assert!(sanitize_subdir(refused).is_err(), "{}", refused);
+    }
+    assert!(sanitize_subdir(&["a"; MAX_SUBDIR_DEPTH + 1].join("/")).is_err());
+
+    let dir = std::env::temp_dir().join(format!("stream-haven-subdir-{}", std::process::id()));
+    let nested = confined_dir(&dir, "Music/Live").unwrap();
+    assert_eq!(nested, dir.canonicalize().unwrap().join("Music").join("Live"));
+    assert!(nested.is_dir());
+    let path = confined_path(&nested, "track.mp3").unwrap();
+    std::fs::write(&path, b"x").unwrap();
+    assert!(existing_download(&dir, &path).is_ok());
+    std::fs::write(dir.join("file"), b"x").unwrap();
+    assert!(confined_dir(&dir, "file/inner").is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn additional_files_share_the_main_stem() {
+    assert_eq!(with_suffix("movie.mp4", ".srt").unwrap(), "movie.srt");
+    assert_eq!(with_suffix("movie.2024.mkv", ".en.vtt").unwrap(), "movie.2024.en.vtt");
+    assert_eq!(with_suffix("stream", ".srt").unwrap(), "stream.srt");
+    assert_eq!(validate_suffix(" .en.srt ").unwrap(), ".en.srt");
+    assert!(validate_suffix("srt").is_err());
+    assert!(validate_suffix("./../x").is_err());
+    assert!(validate_suffix(".a/b").is_err());
+    assert!(validate_suffix(".").is_err());
+  }
+
+  #[test]
+  fn falls_back_to_url_path() {
+    assert_eq!(from_url("https://host/files/My%20Video.mp4?x=1").as_deref(), Some("My Video.mp4"));
+    assert_eq!(from_url("https://host/download/"), Some("download".to_string()));
+    assert_eq!(from_url("https://host/"), None);
+  }
+}
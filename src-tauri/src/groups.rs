@@ -0,0 +1,163 @@
+// Downloads queued together by one batch_download call share a group id, so
+// the UI can show them as one item, like "Album: 7/10 tracks done". The id is
+// kept on each DownloadInfo; once every member has finished, one way or
+// another, "download://group-complete" is emitted.
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState, DownloadInfo};
+
+// Combined progress of a group's members
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct GroupProgress {
+  group_id: String,
+  total: usize,
+  completed: usize,
+  // "error", "corrupt" or "missing"
+  failed: usize,
+  cancelled: usize,
+  // Queued, running, retrying or post-processing
+  active: usize,
+  // Over the members that weren't cancelled; total_bytes and percentage are
+  // None while any of them has an unknown size
+  bytes_downloaded: u64,
+  total_bytes: Option<u64>,
+  percentage: Option<f64>,
+  speed_bytes_per_sec: f64,
+  // None when a size is unknown, nothing is transferring, or a member is
+  // paused or waiting for its start time
+  eta_seconds: Option<u64>,
+}
+
+#[derive(Serialize, Clone)]
+struct GroupCompleteEvent<'a> {
+  group_id: &'a str,
+  total: usize,
+  completed: usize,
+  failed: usize,
+  cancelled: usize,
+}
+
+fn is_finished(download: &DownloadInfo) -> bool {
+  matches!(download.status.as_str(), "completed" | "cancelled" | "error" | "missing" | "corrupt")
+}
+
+// None when no download belongs to the group
+pub(crate) fn progress(
+  downloads: &HashMap<String, DownloadInfo>,
+  group_id: &str,
+  limit: usize,
+) -> Option<GroupProgress> {
+  let members: Vec<&DownloadInfo> =
+    downloads.values().filter(|download| download.group_id.as_deref() == Some(group_id)).collect();
+  if members.is_empty() {
+    return None;
+  }
+  let count = |statuses: &[&str]| {
+    members.iter().filter(|download| statuses.contains(&download.status.as_str())).count()
+  };
+  let counted: Vec<&DownloadInfo> =
+    members.iter().copied().filter(|download| download.status != "cancelled").collect();
+  let bytes_downloaded = counted.iter().map(|download| download.bytes_downloaded).sum();
+  // A completed file is as big as what was written
+  let total_bytes = counted
+    .iter()
+    .map(|download| {
+      download.total_bytes.or_else(|| (download.status == "completed").then_some(download.bytes_downloaded))
+    })
+    .sum::<Option<u64>>();
+  let percentage = total_bytes
+    .map(|total| if total == 0 { 100.0 } else { (bytes_downloaded as f64 / total as f64 * 100.0).min(100.0) });
+  let waiting = members.iter().any(|download| !is_finished(download) && !download.is_in_progress());
+  let eta_seconds = if waiting { None } else { crate::queue_eta(members.iter().copied(), limit) };
+  Some(GroupProgress {
+    group_id: group_id.to_string(),
+    total: members.len(),
+    completed: count(&["completed"]),
+    failed: count(&["error", "corrupt", "missing"]),
+    cancelled: count(&["cancelled"]),
+    active: members.iter().filter(|download| download.is_in_progress()).count(),
+    bytes_downloaded,
+    total_bytes,
+    percentage,
+    speed_bytes_per_sec: members.iter().map(|download| download.speed_bytes_per_sec).sum(),
+    eta_seconds,
+  })
+}
+
+// Called when a member starts running, so its group is announced again once
+// it finishes
+pub(crate) fn member_started(state: &AppState, group_id: &str) {
+  state.announced_groups.lock_or_recover().remove(group_id);
+}
+
+// Called when a download's task ends; announces its group once every member
+// has finished
+pub(crate) async fn member_finished(app: &AppHandle, download_id: &str) {
+  let state = app.state::<AppState>();
+  let downloads = state.downloads.lock().await;
+  let Some(group_id) = downloads.get(download_id).and_then(|download| download.group_id.clone()) else {
+    return;
+  };
+  let members: Vec<&DownloadInfo> =
+    downloads.values().filter(|download| download.group_id.as_deref() == Some(group_id.as_str())).collect();
+  // Checked and marked under the downloads lock, so members finishing at the
+  // same time announce the group once
+  if !members.iter().all(|download| is_finished(download))
+    || !state.announced_groups.lock_or_recover().insert(group_id.clone())
+  {
+    return;
+  }
+  let event = GroupCompleteEvent {
+    group_id: &group_id,
+    total: members.len(),
+    completed: members.iter().filter(|download| download.status == "completed").count(),
+    failed: members
+      .iter()
+      .filter(|download| matches!(download.status.as_str(), "error" | "corrupt" | "missing"))
+      .count(),
+    cancelled: members.iter().filter(|download| download.status == "cancelled").count(),
+  };
+  log::info!("Download group {} finished: {} of {} completed", group_id, event.completed, event.total);
+  if let Err(e) = app.emit("download://group-complete", event) {
+    log::warn!("Failed to emit group complete event for {}: {}", group_id, e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn group_progress_combines_members() {
+    let mut downloads = HashMap::new();
+    let entries = [
+      ("a", "completed", 100, None),
+      ("b", "downloading", 50, Some(100)),
+      ("c", "error", 10, Some(100)),
+      ("d", "cancelled", 5, Some(100)),
+    ];
+    for (id, status, bytes_downloaded, total_bytes) in entries {
+      let download = DownloadInfo {
+        id: id.to_string(),
+        status: status.to_string(),
+        bytes_downloaded,
+        total_bytes,
+        speed_bytes_per_sec: if status == "downloading" { 10.0 } else { 0.0 },
+        group_id: Some("album".to_string()),
+        ..Default::default()
+      };
+      downloads.insert(id.to_string(), download);
+    }
+    downloads.insert("e".to_string(), DownloadInfo { status: "downloading".to_string(), ..Default::default() });
+    let progress = progress(&downloads, "album", 3).unwrap();
+    let counts = (progress.total, progress.completed, progress.failed, progress.cancelled, progress.active);
+    assert_eq!(counts, (4, 1, 1, 1, 1));
+    assert_eq!((progress.bytes_downloaded, progress.total_bytes), (160, Some(300)));
+    assert_eq!(progress.eta_seconds, Some(5));
+    assert!(super::progress(&downloads, "other", 3).is_none());
+  }
+}
@@ -0,0 +1,269 @@
+// SQLite archive of finished downloads. AppState keeps the live queue; every
+// download that reaches a final status is copied here so old entries can be
+// searched after the queue has been cleared.
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState, DownloadInfo};
+
+// File name of the database inside the app data directory
+pub(crate) const HISTORY_FILE: &str = "history.sqlite3";
+// Rows returned by one search when the caller doesn't ask for fewer
+pub(crate) const DEFAULT_SEARCH_LIMIT: u32 = 200;
+pub(crate) const MAX_SEARCH_LIMIT: u32 = 1000;
+
+// Schema changes in order. The database's user_version records how many have
+// been applied, so an upgraded app runs only the new ones.
+const MIGRATIONS: &[&str] = &[
+  "CREATE TABLE downloads (
+     id TEXT PRIMARY KEY,
+     url TEXT NOT NULL,
+     filename TEXT NOT NULL,
+     path TEXT NOT NULL,
+     status TEXT NOT NULL,
+     total_bytes INTEGER,
+     bytes_downloaded INTEGER NOT NULL,
+     sha256 TEXT,
+     error TEXT,
+     created_at INTEGER NOT NULL,
+     finished_at INTEGER NOT NULL
+   );
+   CREATE INDEX downloads_filename ON downloads (filename);
+   CREATE INDEX downloads_url ON downloads (url);
+   CREATE INDEX downloads_finished_at ON downloads (finished_at);",
+  // Rows from before this keep a NULL normalized_url and match on url only
+  "ALTER TABLE downloads ADD COLUMN normalized_url TEXT;
+   CREATE INDEX downloads_normalized_url ON downloads (normalized_url);",
+];
+
+#[derive(Serialize)]
+pub(crate) struct HistoryEntry {
+  id: String,
+  url: String,
+  filename: String,
+  path: String,
+  status: String,
+  total_bytes: Option<u64>,
+  bytes_downloaded: u64,
+  sha256: Option<String>,
+  error: Option<String>,
+  created_at: i64,
+  finished_at: i64,
+}
+
+// Filters for search; every field is optional and they combine with AND
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct HistoryQuery {
+  // Substring of the file name or URL, case-insensitive
+  pub text: Option<String>,
+  pub status: Option<String>,
+  // Finish time range in milliseconds since the Unix epoch, inclusive
+  pub from: Option<i64>,
+  pub to: Option<i64>,
+  pub limit: Option<u32>,
+}
+
+pub(crate) struct History {
+  conn: Mutex<Connection>,
+}
+
+impl History {
+  pub(crate) fn open(path: &Path) -> Result<Self, String> {
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)
+        .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let mut conn = Connection::open(path)
+      .map_err(|e| format!("Failed to open history database {}: {}", path.display(), e))?;
+    migrate(&mut conn)?;
+    Ok(Self { conn: Mutex::new(conn) })
+  }
+
+  // Inserts or refreshes the download's row
+  pub(crate) fn record(&self, download: &DownloadInfo) -> Result<(), String> {
+    let conn = self.conn.lock_or_recover();
+    conn
+      .execute(
+        "INSERT OR REPLACE INTO downloads
+           (id, url, filename, path, status, total_bytes, bytes_downloaded, sha256, error, created_at, finished_at,
+            normalized_url)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+          download.id,
+          download.url,
+          download.filename,
+          download.path.to_string_lossy(),
+          download.status,
+          download.total_bytes.map(|total| total as i64),
+          download.bytes_downloaded as i64,
+          download.sha256,
+          download.error,
+          download.created_at,
+          chrono::Utc::now().timestamp_millis(),
+          download.normalized_url,
+        ],
+      )
+      .map(|_| ())
+      .map_err(|e| format!("Failed to record {} in history: {}", download.id, e))
+  }
+
+  pub(crate) fn search(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, String> {
+    let pattern = query
+      .text
+      .as_deref()
+      .map(str::trim)
+      .filter(|text| !text.is_empty())
+      .map(|text| format!("%{}%", escape_like(text)));
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+
+    let conn = self.conn.lock_or_recover();
+    let mut statement = conn
+      .prepare_cached(
+        "SELECT id, url, filename, path, status, total_bytes, bytes_downloaded, sha256, error, created_at, finished_at
+         FROM downloads
+         WHERE (?1 IS NULL OR filename LIKE ?1 ESCAPE '\\' OR url LIKE ?1 ESCAPE '\\'
+                OR normalized_url LIKE ?1 ESCAPE '\\')
+           AND (?2 IS NULL OR status = ?2)
+           AND (?3 IS NULL OR finished_at >= ?3)
+           AND (?4 IS NULL OR finished_at <= ?4)
+         ORDER BY finished_at DESC
+         LIMIT ?5",
+      )
+      .map_err(|e| format!("Failed to query history: {}", e))?;
+    let rows = statement
+      .query_map(params![pattern, query.status, query.from, query.to, limit], |row| {
+        Ok(HistoryEntry {
+          id: row.get(0)?,
+          url: row.get(1)?,
+          filename: row.get(2)?,
+          path: row.get(3)?,
+          status: row.get(4)?,
+          total_bytes: row.get::<_, Option<i64>>(5)?.map(|total| total as u64),
+          bytes_downloaded: row.get::<_, i64>(6)? as u64,
+          sha256: row.get(7)?,
+          error: row.get(8)?,
+          created_at: row.get(9)?,
+          finished_at: row.get(10)?,
+        })
+      })
+      .map_err(|e| format!("Failed to query history: {}", e))?;
+    rows
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|e| format!("Failed to read history: {}", e))
+  }
+}
+
+// Copies the download into the archive once it has reached a final status
+pub(crate) async fn archive(app: &AppHandle, download_id: &str) {
+  let state = app.state::<AppState>();
+  if state.history.is_none() {
+    return;
+  }
+  let download = state
+    .downloads
+    .lock()
+    .await
+    .get(download_id)
+    .filter(|download| matches!(download.status.as_str(), "completed" | "cancelled" | "error"))
+    .cloned();
+  let Some(download) = download else { return };
+  let app = app.clone();
+  let recorded = tauri::async_runtime::spawn_blocking(move || {
+    match &app.state::<AppState>().history {
+      Some(history) => history.record(&download),
+      None => Ok(()),
+    }
+  })
+  .await;
+  match recorded {
+    Ok(Ok(())) => {}
+    Ok(Err(e)) => log::warn!("{}", e),
+    Err(e) => log::warn!("Recording {} in history failed: {}", download_id, e),
+  }
+}
+
+// Applies the migrations the database hasn't seen yet, each in its own
+// transaction together with the version bump
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+  let version: u32 = conn
+    .query_row("PRAGMA user_version", [], |row| row.get(0))
+    .optional()
+    .map_err(|e| format!("Failed to read history schema version: {}", e))?
+    .unwrap_or(0);
+  if version as usize > MIGRATIONS.len() {
+    return Err(format!(
+      "History database has schema version {}, newer than this app supports ({})",
+      version,
+      MIGRATIONS.len()
+    ));
+  }
+  for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+    let transaction = conn
+      .transaction()
+      .map_err(|e| format!("Failed to start history migration: {}", e))?;
+    transaction
+      .execute_batch(migration)
+      .and_then(|_| transaction.pragma_update(None, "user_version", index as u32 + 1))
+      .and_then(|_| transaction.commit())
+      .map_err(|e| format!("History migration {} failed: {}", index + 1, e))?;
+    log::info!("History database migrated to version {}", index + 1);
+  }
+  Ok(())
+}
+
+// Makes % and _ in user input match literally
+fn escape_like(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    if matches!(c, '%' | '_' | '\\') {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn records_and_searches_finished_downloads() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-history-{}", uuid::Uuid::new_v4()));
+    let history = History::open(&dir.join(HISTORY_FILE)).unwrap();
+    for (id, filename, status) in [("a", "holiday_100%.mp4", "completed"), ("b", "talk.mp4", "error")] {
+      let download = DownloadInfo {
+        id: id.to_string(),
+        url: format!("https://example.com/{}", filename),
+        filename: filename.to_string(),
+        status: status.to_string(),
+        ..Default::default()
+      };
+      history.record(&download).unwrap();
+    }
+
+    let search = |text: &str, status: Option<&str>| {
+      let query = HistoryQuery {
+        text: Some(text.to_string()),
+        status: status.map(str::to_string),
+        ..Default::default()
+      };
+      history.search(&query).unwrap().into_iter().map(|entry| entry.id).collect::<Vec<_>>()
+    };
+    assert_eq!(search("HOLIDAY", None), vec!["a"]);
+    assert_eq!(search("100%", None), vec!["a"]);
+    assert_eq!(search("_", None), vec!["a"]);
+    assert_eq!(search("mp4", Some("error")), vec!["b"]);
+    drop(history);
+
+    // Reopening runs no migration twice
+    assert!(History::open(&dir.join(HISTORY_FILE)).is_ok());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
@@ -0,0 +1,310 @@
+use url::Url;
+
+// Content types servers use for HLS playlists
+const PLAYLIST_CONTENT_TYPES: &[&str] = &[
+  "application/vnd.apple.mpegurl",
+  "application/x-mpegurl",
+  "audio/mpegurl",
+  "audio/x-mpegurl",
+];
+
+// One rendition listed by a master playlist
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Variant {
+  pub uri: Url,
+  // Peak bits per second from BANDWIDTH
+  pub bandwidth: u64,
+  // Vertical resolution from RESOLUTION, when given
+  pub height: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Segment {
+  pub uri: Url,
+  pub duration: f64,
+  // First and last byte (inclusive) within uri, from EXT-X-BYTERANGE; None
+  // for a segment that is the whole resource
+  pub range: Option<(u64, u64)>,
+  // An EXT-X-MAP initialization section, written ahead of the media segments
+  // that use it. Its duration is zero.
+  pub init: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Playlist {
+  Master(Vec<Variant>),
+  Media(Vec<Segment>),
+}
+
+// True when the response is an HLS playlist, judged by its content type or,
+// for servers that send a generic type, by a .m3u8 path
+pub(crate) fn is_playlist(content_type: Option<&str>, url: &Url) -> bool {
+  let by_type = content_type
+    .and_then(|value| value.split(';').next())
+    .map(|value| value.trim().to_ascii_lowercase())
+    .is_some_and(|value| PLAYLIST_CONTENT_TYPES.contains(&value.as_str()));
+  by_type || has_playlist_extension(url)
+}
+
+pub(crate) fn has_playlist_extension(url: &Url) -> bool {
+  url.path().to_ascii_lowercase().ends_with(".m3u8")
+}
+
+// Parses a master or media playlist, resolving URIs against the playlist URL.
+// Live playlists (no EXT-X-ENDLIST) and encrypted segments are rejected since
+// neither can be saved as a single file. EXT-X-MAP sections are returned as
+// init segments in front of the segments they apply to. A malformed
+// EXT-X-BYTERANGE or EXT-X-MAP fails the parse, since guessing would produce a
+// file of the wrong size.
+pub(crate) fn parse(text: &str, base: &Url) -> Result<Playlist, String> {
+  let mut lines = text
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty());
+  if lines.next() != Some("#EXTM3U") {
+    return Err("Not an HLS playlist: missing #EXTM3U header".to_string());
+  }
+
+  let mut variants = Vec::new();
+  let mut segments = Vec::new();
+  let mut pending_variant: Option<(u64, Option<u32>)> = None;
+  let mut pending_duration: Option<f64> = None;
+  let mut pending_range: Option<(u64, Option<u64>)> = None;
+  // Resource and next byte of the last ranged segment, where a range without
+  // an offset continues
+  let mut previous_range: Option<(Url, u64)> = None;
+  // The EXT-X-MAP in effect and whether it was already emitted
+  let mut map: Option<Segment> = None;
+  let mut map_emitted = false;
+  let mut ended = false;
+
+  for line in lines {
+    if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+      let attributes = parse_attributes(attributes);
+      let bandwidth = attribute(&attributes, "BANDWIDTH")
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("Variant without a valid BANDWIDTH: {}", line))?;
+      let height = attribute(&attributes, "RESOLUTION")
+        .and_then(|value| value.split_once('x'))
+        .and_then(|(_, height)| height.parse().ok());
+      pending_variant = Some((bandwidth, height));
+    } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+      let duration = info.split(',').next().unwrap_or_default().trim();
+      let duration = duration
+        .parse()
+        .map_err(|_| format!("Invalid segment duration: {}", line))?;
+      pending_duration = Some(duration);
+    } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+      pending_range = Some(parse_byte_range(value).ok_or_else(|| format!("Invalid #EXT-X-BYTERANGE: {}", line))?);
+    } else if let Some(attributes) = line.strip_prefix("#EXT-X-MAP:") {
+      let attributes = parse_attributes(attributes);
+      let uri = attribute(&attributes, "URI").ok_or_else(|| format!("#EXT-X-MAP without a URI: {}", line))?;
+      let uri = base
+        .join(uri)
+        .map_err(|_| format!("Invalid URI in playlist: {}", line))?;
+      // Unlike a segment's, a map's byte range always names its offset
+      let range = match attribute(&attributes, "BYTERANGE").map(parse_byte_range) {
+        None => None,
+        Some(Some((length, Some(offset)))) => Some(span(length, offset, line)?),
+        Some(_) => return Err(format!("Invalid BYTERANGE in #EXT-X-MAP: {}", line)),
+      };
+      map = Some(Segment { uri, duration: 0.0, range, init: true });
+      map_emitted = false;
+    } else if let Some(attributes) = line.strip_prefix("#EXT-X-KEY:") {
+      let attributes = parse_attributes(attributes);
+      if attribute(&attributes, "METHOD").is_some_and(|method| method != "NONE") {
+        return Err("Encrypted HLS streams are not supported".to_string());
+      }
+    } else if line == "#EXT-X-ENDLIST" {
+      ended = true;
+    } else if line.starts_with('#') {
+      // Tags we don't need, and comments
+    } else {
+      let uri = base
+        .join(line)
+        .map_err(|_| format!("Invalid URI in playlist: {}", line))?;
+      if let Some((bandwidth, height)) = pending_variant.take() {
+        variants.push(Variant { uri, bandwidth, height });
+      } else if let Some(duration) = pending_duration.take() {
+        let range = match pending_range.take() {
+          None => None,
+          Some((length, offset)) => {
+            let offset = match (offset, &previous_range) {
+              (Some(offset), _) => offset,
+              (None, Some((previous, next))) if *previous == uri => *next,
+              (None, _) => {
+                return Err(format!(
+                  "#EXT-X-BYTERANGE without an offset must follow a range of the same URI: {}",
+                  line
+                ))
+              }
+            };
+            let range = span(length, offset, line)?;
+            previous_range = Some((uri.clone(), range.1 + 1));
+            Some(range)
+          }
+        };
+        if let (Some(map), false) = (&map, map_emitted) {
+          segments.push(map.clone());
+          map_emitted = true;
+        }
+        segments.push(Segment { uri, duration, range, init: false });
+      } else {
+        return Err(format!("URI without a preceding #EXTINF or #EXT-X-STREAM-INF: {}", line));
+      }
+    }
+  }
+
+  if pending_range.is_some() {
+    return Err("#EXT-X-BYTERANGE without a following segment".to_string());
+  }
+  if !variants.is_empty() {
+    return Ok(Playlist::Master(variants));
+  }
+  if segments.is_empty() {
+    return Err("Playlist contains no segments".to_string());
+  }
+  if !ended {
+    return Err("Live HLS streams are not supported".to_string());
+  }
+  Ok(Playlist::Media(segments))
+}
+
+// Picks the rendition to download. Quality is "highest" (the default),
+// "lowest", or a height such as "720" or "720p", which selects the best
+// rendition no taller than that, falling back to the smallest one.
+pub(crate) fn select_variant<'a>(variants: &'a [Variant], quality: Option<&str>) -> Option<&'a Variant> {
+  let highest = variants.iter().max_by_key(|variant| variant.bandwidth);
+  let lowest = variants.iter().min_by_key(|variant| variant.bandwidth);
+  let quality = quality.map(|value| value.trim().to_ascii_lowercase());
+  match quality.as_deref() {
+    None | Some("") | Some("highest") | Some("best") => highest,
+    Some("lowest") | Some("worst") => lowest,
+    Some(value) => match value.trim_end_matches('p').parse::<u32>() {
+      Ok(max_height) => variants
+        .iter()
+        .filter(|variant| variant.height.is_some_and(|height| height <= max_height))
+        .max_by_key(|variant| (variant.height, variant.bandwidth))
+        .or(lowest),
+      Err(_) => highest,
+    },
+  }
+}
+
+// "<length>[@<offset>]"
+fn parse_byte_range(value: &str) -> Option<(u64, Option<u64>)> {
+  let (length, offset) = match value.trim().split_once('@') {
+    Some((length, offset)) => (length, Some(offset.trim().parse().ok()?)),
+    None => (value.trim(), None),
+  };
+  Some((length.trim().parse().ok()?, offset))
+}
+
+// First and last byte of a range, rejecting empty or overflowing ones
+fn span(length: u64, offset: u64, line: &str) -> Result<(u64, u64), String> {
+  offset
+    .checked_add(length)
+    .filter(|_| length > 0)
+    .map(|end| (offset, end - 1))
+    .ok_or_else(|| format!("Invalid byte range: {}", line))
+}
+
+// Splits an attribute list on commas outside quoted strings
+fn parse_attributes(list: &str) -> Vec<(String, String)> {
+  let mut attributes = Vec::new();
+  let mut current = String::new();
+  let mut quoted = false;
+  for c in list.chars().chain(std::iter::once(',')) {
+    match c {
+      '"' => {
+        quoted = !quoted;
+        current.push(c);
+      }
+      ',' if !quoted => {
+        if let Some((name, value)) = current.split_once('=') {
+          attributes.push((name.trim().to_string(), value.trim().trim_matches('"').to_string()));
+        }
+        current.clear();
+      }
+      _ => current.push(c),
+    }
+  }
+  attributes
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+  attributes
+    .iter()
+    .find(|(key, _)| key == name)
+    .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn base() -> Url {
+    Url::parse("https://cdn.example.com/video/master.m3u8").unwrap()
+  }
+
+  #[test]
+  fn parses_media_playlist_with_relative_segments() {
+    let text = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:9.5,\nseg0.ts\n#EXTINF:4,title\n/abs/seg1.ts\n#EXT-X-ENDLIST\n";
+    let Playlist::Media(segments) = parse(text, &base()).unwrap() else { panic!("expected media") };
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0].uri.as_str(), "https://cdn.example.com/video/seg0.ts");
+    assert_eq!(segments[1].uri.as_str(), "https://cdn.example.com/abs/seg1.ts");
+    assert_eq!(segments[0].duration, 9.5);
+  }
+
+  #[test]
+  fn parses_master_playlist_and_selects_quality() {
+    let text = "#EXTM3U\n\
+      #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"\nlow.m3u8\n\
+      #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080\nhigh.m3u8\n\
+      #EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720\nmid.m3u8\n";
+    let Playlist::Master(variants) = parse(text, &base()).unwrap() else { panic!("expected master") };
+    assert_eq!(variants.len(), 3);
+    assert_eq!(select_variant(&variants, None).unwrap().height, Some(1080));
+    assert_eq!(select_variant(&variants, Some("lowest")).unwrap().height, Some(360));
+    assert_eq!(select_variant(&variants, Some("720p")).unwrap().height, Some(720));
+    assert_eq!(select_variant(&variants, Some("240")).unwrap().height, Some(360));
+  }
+
+  #[test]
+  fn rejects_live_encrypted_and_malformed_playlists() {
+    assert!(parse("#EXTM3U\n#EXTINF:4,\na.ts\n", &base()).is_err());
+    assert!(parse("#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"k\"\n#EXTINF:4,\na.ts\n#EXT-X-ENDLIST\n", &base()).is_err());
+    assert!(parse("<html></html>", &base()).is_err());
+    assert!(parse("#EXTM3U\na.ts\n#EXT-X-ENDLIST\n", &base()).is_err());
+  }
+
+  #[test]
+  fn parses_byte_ranges_and_init_sections() {
+    let text = "#EXTM3U\n#EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720@0\"\n\
+      #EXTINF:4,\n#EXT-X-BYTERANGE:1000@720\nmain.mp4\n\
+      #EXTINF:4,\n#EXT-X-BYTERANGE:500\nmain.mp4\n#EXT-X-ENDLIST\n";
+    let Playlist::Media(segments) = parse(text, &base()).unwrap() else { panic!("expected media") };
+    assert_eq!(segments.len(), 3);
+    assert!(segments[0].init);
+    assert_eq!(segments[0].range, Some((0, 719)));
+    assert_eq!(segments[1].range, Some((720, 1719)));
+    assert_eq!(segments[2].range, Some((1720, 2219)));
+    assert_eq!(segments[2].uri.as_str(), "https://cdn.example.com/video/main.mp4");
+
+    let continued = "#EXTM3U\n#EXTINF:4,\n#EXT-X-BYTERANGE:10@0\na.ts\n#EXTINF:4,\n#EXT-X-BYTERANGE:10\nb.ts\n#EXT-X-ENDLIST\n";
+    assert!(parse(continued, &base()).unwrap_err().contains("same URI"));
+    assert!(parse("#EXTM3U\n#EXTINF:4,\n#EXT-X-BYTERANGE:ten@0\na.ts\n#EXT-X-ENDLIST\n", &base()).is_err());
+    assert!(parse("#EXTM3U\n#EXTINF:4,\n#EXT-X-BYTERANGE:0@0\na.ts\n#EXT-X-ENDLIST\n", &base()).is_err());
+    assert!(parse("#EXTM3U\n#EXT-X-MAP:BYTERANGE=\"1@0\"\n#EXTINF:4,\na.ts\n#EXT-X-ENDLIST\n", &base()).is_err());
+    assert!(parse("#EXTM3U\n#EXT-X-MAP:URI=\"i.mp4\",BYTERANGE=\"10\"\n#EXTINF:4,\na.ts\n#EXT-X-ENDLIST\n", &base()).is_err());
+  }
+
+  #[test]
+  fn detects_playlists_by_type_or_extension() {
+    let plain = Url::parse("https://example.com/stream").unwrap();
+    assert!(is_playlist(Some("application/vnd.apple.mpegurl; charset=utf-8"), &plain));
+    assert!(is_playlist(Some("text/plain"), &base()));
+    assert!(!is_playlist(Some("video/mp4"), &plain));
+  }
+}
@@ -0,0 +1,72 @@
+// Holding downloads back while the system sleeps, in offline mode or on a
+// metered connection, and letting them go again afterwards. Each reason is
+// kept in hold_reason, so the downloads held for one are released on their own.
+use tauri::Manager;
+
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState};
+
+// Moves everything running or waiting, retries included, to `status` with
+// `reason` and waits for the tasks to close their files. Returns how many
+// downloads were held.
+pub(crate) async fn hold_active(app: &AppHandle, reason: &str, status: &str) -> usize {
+  let state = app.state::<AppState>();
+  let tokens: Vec<_> = state
+    .active
+    .lock_or_recover()
+    .iter()
+    .map(|(id, token)| (id.clone(), token.clone()))
+    .collect();
+  let mut held = Vec::new();
+  {
+    let mut downloads = state.downloads.lock().await;
+    for (id, _) in &tokens {
+      if let Some(download) = downloads.get_mut(id).filter(|download| download.is_in_progress()) {
+        download.status = status.to_string();
+        download.hold_reason = Some(reason.to_string());
+        held.push(id.clone());
+      }
+    }
+  }
+  for (id, token) in &tokens {
+    if held.contains(id) {
+      token.cancel();
+    }
+  }
+  for id in &held {
+    crate::downloader::stop_and_wait(&state, id).await;
+  }
+  crate::persistence::save(&state).await;
+  held.len()
+}
+
+// Restarts the downloads held for `reason` in queue order; the concurrency
+// limit decides how many run at once. Only ones still in one of `statuses`
+// go, so those the user paused or resumed in the meantime are left alone.
+// Returns how many were restarted.
+pub(crate) async fn release(app: &AppHandle, reason: &str, statuses: &[&str]) -> usize {
+  let state = app.state::<AppState>();
+  let active: Vec<String> = state.active.lock_or_recover().keys().cloned().collect();
+  let mut held: Vec<(i64, String)> = {
+    let mut downloads = state.downloads.lock().await;
+    downloads
+      .values_mut()
+      .filter(|download| download.hold_reason.as_deref() == Some(reason))
+      .filter_map(|download| {
+        download.hold_reason = None;
+        (statuses.contains(&download.status.as_str()) && !active.contains(&download.id)).then(|| {
+          download.status = "queued".to_string();
+          (download.queue_position, download.id.clone())
+        })
+      })
+      .collect()
+  };
+  crate::persistence::save(&state).await;
+  held.sort();
+  for (_, id) in &held {
+    if let Err(e) = crate::downloader::spawn(app, id.clone()) {
+      log::warn!("Failed to resume download {} held for {}: {}", id, reason, e);
+    }
+  }
+  held.len()
+}
@@ -0,0 +1,89 @@
+// Deployment-level host rules, applied on top of the private network checks.
+// A rule with a leading dot (".example.com") matches that domain and all of
+// its subdomains; any other rule matches the host exactly.
+#[derive(Default)]
+pub(crate) struct HostFilter {
+  // When set, only hosts matching one of these rules may be downloaded from
+  allowlist: Option<Vec<String>>,
+  // Hosts matching any of these are always rejected
+  blocklist: Vec<String>,
+}
+
+impl HostFilter {
+  pub(crate) fn set_allowlist(&mut self, rules: Option<Vec<String>>) {
+    self.allowlist = rules.map(normalize_rules);
+  }
+
+  pub(crate) fn set_blocklist(&mut self, rules: Vec<String>) {
+    self.blocklist = normalize_rules(rules);
+  }
+
+  // Returns an error message when the host may not be used
+  pub(crate) fn check(&self, host: &str) -> Result<(), String> {
+    let host = normalize(host);
+    if self.blocklist.iter().any(|rule| matches(rule, &host)) {
+      return Err(format!("Downloads from {} are blocked", host));
+    }
+    if let Some(allowlist) = &self.allowlist {
+      if !allowlist.iter().any(|rule| matches(rule, &host)) {
+        return Err(format!("{} is not on the list of allowed hosts", host));
+      }
+    }
+    Ok(())
+  }
+}
+
+pub(crate) fn normalize(host: &str) -> String {
+  host
+    .trim()
+    .trim_start_matches('[')
+    .trim_end_matches(']')
+    .trim_end_matches('.')
+    .to_lowercase()
+}
+
+fn normalize_rules(rules: Vec<String>) -> Vec<String> {
+  rules
+    .iter()
+    .map(|rule| normalize(rule))
+    .filter(|rule| !rule.is_empty() && rule != ".")
+    .collect()
+}
+
+fn matches(rule: &str, host: &str) -> bool {
+  match rule.strip_prefix('.') {
+    Some(domain) => host == domain || host.ends_with(rule),
+    None => host == rule,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn suffix_rules_match_subdomains() {
+    let mut filter = HostFilter::default();
+    filter.set_allowlist(Some(vec![".Example.com".to_string()]));
+    assert!(filter.check("example.com").is_ok());
+    assert!(filter.check("cdn.example.com.").is_ok());
+    assert!(filter.check("badexample.com").is_err());
+    assert!(filter.check("example.org").is_err());
+  }
+
+  #[test]
+  fn blocklist_wins_over_allowlist() {
+    let mut filter = HostFilter::default();
+    filter.set_allowlist(Some(vec![".example.com".to_string()]));
+    filter.set_blocklist(vec!["ads.example.com".to_string()]);
+    assert!(filter.check("ads.example.com").is_err());
+    assert!(filter.check("x.ads.example.com").is_ok());
+    assert!(filter.check("www.example.com").is_ok());
+  }
+
+  #[test]
+  fn no_rules_allows_everything() {
+    let filter = HostFilter::default();
+    assert!(filter.check("anything.test").is_ok());
+  }
+}
@@ -0,0 +1,359 @@
+// Download pages that stand between a link and its file: an HTML page that
+// forwards with a meta refresh or a script, or that only offers a download
+// link. Used by downloads with DownloadOptions::follow_interstitials, which
+// follow the page to the file instead of saving the page.
+use url::Url;
+
+// Most of a page that is read to look for the file
+pub(crate) const MAX_PAGE_BYTES: usize = 512 * 1024;
+// Pages followed in a row before giving up
+pub(crate) const MAX_PAGES: usize = 3;
+// Characters of page text quoted when no file is found
+const SNIPPET_CHARS: usize = 160;
+
+// Extensions a link must end in to count as the file on a page without a
+// link marked with the download attribute
+const FILE_EXTENSIONS: &[&str] = &[
+  "mp4", "mkv", "webm", "mov", "avi", "m4v", "flv", "ts", "m3u8", "mp3", "m4a", "aac", "flac", "wav", "ogg",
+  "opus", "zip", "7z", "rar", "tar", "gz", "tgz", "xz", "bz2", "iso", "exe", "msi", "dmg", "pkg", "deb", "rpm",
+  "apk", "pdf", "epub",
+];
+
+pub(crate) fn is_html(content_type: Option<&str>) -> bool {
+  let mime = content_type.and_then(|value| value.split(';').next()).unwrap_or_default().trim();
+  mime.eq_ignore_ascii_case("text/html") || mime.eq_ignore_ascii_case("application/xhtml+xml")
+}
+
+// A download named like a web page really is after the page
+pub(crate) fn wants_page(filename: &str) -> bool {
+  let extension = filename.rsplit_once('.').map(|(_, extension)| extension).unwrap_or_default();
+  extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm")
+}
+
+// Where the page leads, resolved against its URL: a meta refresh first, then
+// a script assigning window.location, then a link marked with the download
+// attribute, then the one link to a file with a known extension. Several
+// different file links are too ambiguous to pick from, so they give None.
+pub(crate) fn find_target(html: &str, base: &Url) -> Option<Url> {
+  let tags = tags(html);
+  let resolve = |value: &str| {
+    let value = value.trim();
+    if value.is_empty() || value.starts_with('#') {
+      return None;
+    }
+    base.join(value).ok().filter(|url| matches!(url.scheme(), "http" | "https") && url != base)
+  };
+
+  let refresh = tags.iter().find_map(|tag| {
+    let equiv = tag.attr("http-equiv")?;
+    equiv.eq_ignore_ascii_case("refresh").then(|| tag.attr("content").and_then(refresh_url)).flatten()
+  });
+  if let Some(url) = refresh.and_then(resolve) {
+    return Some(url);
+  }
+  let scripted = tags
+    .iter()
+    .filter_map(|tag| tag.script)
+    .find_map(|script| script_redirect(script).and_then(|url| resolve(&url)));
+  if scripted.is_some() {
+    return scripted;
+  }
+
+  let links: Vec<(&Tag, Url)> = tags
+    .iter()
+    .filter(|tag| tag.name == "a")
+    .filter_map(|tag| Some((tag, resolve(tag.attr("href")?)?)))
+    .collect();
+  if let Some((_, url)) = links.iter().find(|(tag, _)| tag.attr("download").is_some()) {
+    return Some(url.clone());
+  }
+  let mut files = links.into_iter().map(|(_, url)| url).filter(has_file_extension);
+  let first = files.next()?;
+  files.all(|url| url == first).then_some(first)
+}
+
+// The start of the page's visible text, for an error saying what came back
+pub(crate) fn snippet(html: &str) -> String {
+  let text: Vec<&str> = tokens(html)
+    .into_iter()
+    .filter_map(|token| match token {
+      Token::Text(text) => Some(text),
+      Token::Tag(_) => None,
+    })
+    .collect();
+  let text = decode_entities(&text.join(" ")).split_whitespace().collect::<Vec<_>>().join(" ");
+  match text.char_indices().nth(SNIPPET_CHARS) {
+    Some((end, _)) => format!("{}…", &text[..end]),
+    None => text,
+  }
+}
+
+enum Token<'a> {
+  Text(&'a str),
+  Tag(Tag<'a>),
+}
+
+// One start tag: its lowercase name and attributes with entities decoded
+struct Tag<'a> {
+  name: String,
+  attrs: Vec<(String, String)>,
+  // What a <script> contains
+  script: Option<&'a str>,
+  // Bytes the tag itself spans
+  len: usize,
+}
+
+impl Tag<'_> {
+  fn attr(&self, name: &str) -> Option<&str> {
+    self.attrs.iter().find(|(attr, _)| attr == name).map(|(_, value)| value.as_str())
+  }
+
+  // Elements whose contents are text rather than markup
+  fn raw_text(&self) -> Option<&'static str> {
+    match self.name.as_str() {
+      "script" => Some("</script"),
+      "style" => Some("</style"),
+      _ => None,
+    }
+  }
+}
+
+// Every start tag in the page
+fn tags(html: &str) -> Vec<Tag<'_>> {
+  tokens(html)
+    .into_iter()
+    .filter_map(|token| match token {
+      Token::Tag(tag) => Some(tag),
+      Token::Text(_) => None,
+    })
+    .collect()
+}
+
+// Splits the page into text and start tags. Comments, end tags and doctypes
+// are dropped, and what a script or style contains goes with its tag instead
+// of counting as text.
+fn tokens(html: &str) -> Vec<Token<'_>> {
+  let mut tokens = Vec::new();
+  let mut rest = html;
+  while let Some(start) = rest.find('<') {
+    tokens.push(Token::Text(&rest[..start]));
+    rest = &rest[start..];
+    if let Some(comment) = rest.strip_prefix("<!--") {
+      rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+      continue;
+    }
+    if rest[1..].starts_with(['/', '!', '?']) {
+      rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+      continue;
+    }
+    let Some(mut tag) = parse_tag(rest) else {
+      tokens.push(Token::Text("<"));
+      rest = &rest[1..];
+      continue;
+    };
+    rest = &rest[tag.len..];
+    if let Some(raw) = tag.raw_text() {
+      let (text, after) = skip_raw_text(rest, raw);
+      if tag.name == "script" {
+        tag.script = Some(text);
+      }
+      rest = after;
+    }
+    tokens.push(Token::Tag(tag));
+  }
+  tokens.push(Token::Text(rest));
+  tokens
+}
+
+// Splits raw text at the end tag that closes it, matched case-insensitively
+fn skip_raw_text<'a>(rest: &'a str, end_tag: &str) -> (&'a str, &'a str) {
+  let end = rest.to_ascii_lowercase().find(end_tag).unwrap_or(rest.len());
+  let after = rest[end..].find('>').map_or("", |close| &rest[end + close + 1..]);
+  (&rest[..end], after)
+}
+
+// Parses the start tag `text` begins with; None for anything else, like an
+// end tag or a stray '<'
+fn parse_tag(text: &str) -> Option<Tag<'_>> {
+  let bytes = text.as_bytes();
+  let name_end = 1 + text[1..].find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(text.len() - 1);
+  if name_end == 1 || !bytes[1].is_ascii_alphabetic() {
+    return None;
+  }
+  let name = text[1..name_end].to_ascii_lowercase();
+  let mut attrs = Vec::new();
+  let mut at = name_end;
+  loop {
+    while at < bytes.len() && (bytes[at].is_ascii_whitespace() || bytes[at] == b'/') {
+      at += 1;
+    }
+    match bytes.get(at) {
+      None => return None,
+      Some(b'>') => return Some(Tag { name, attrs, script: None, len: at + 1 }),
+      _ => {}
+    }
+    let attr_len = text[at..].find(|c: char| c.is_ascii_whitespace() || "=>/".contains(c));
+    let attr_end = at + attr_len.unwrap_or(text.len() - at);
+    let attr = text[at..attr_end].to_ascii_lowercase();
+    at = attr_end;
+    while at < bytes.len() && bytes[at].is_ascii_whitespace() {
+      at += 1;
+    }
+    let mut value = String::new();
+    if bytes.get(at) == Some(&b'=') {
+      at += 1;
+      while at < bytes.len() && bytes[at].is_ascii_whitespace() {
+        at += 1;
+      }
+      let raw = match bytes.get(at) {
+        Some(&quote) if quote == b'"' || quote == b'\'' => {
+          let end = at + 1 + text[at + 1..].find(quote as char)?;
+          let raw = &text[at + 1..end];
+          at = end + 1;
+          raw
+        }
+        _ => {
+          let end = at + text[at..].find(|c: char| c.is_ascii_whitespace() || c == '>').unwrap_or(text.len() - at);
+          let raw = &text[at..end];
+          at = end;
+          raw
+        }
+      };
+      value = decode_entities(raw);
+    }
+    if !attr.is_empty() {
+      attrs.push((attr, value));
+    }
+  }
+}
+
+// Named entities that show up in URLs and page text, and numeric ones
+fn decode_entities(text: &str) -> String {
+  let mut decoded = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(start) = rest.find('&') {
+    decoded.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let entity = rest[1..].find(';').filter(|end| *end <= 10).map(|end| &rest[1..end + 1]);
+    let character = entity.and_then(|entity| match entity {
+      "amp" => Some('&'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "nbsp" => Some(' '),
+      _ => {
+        let number = entity.strip_prefix('#')?;
+        let code = match number.strip_prefix(['x', 'X']) {
+          Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+          None => number.parse().ok()?,
+        };
+        char::from_u32(code)
+      }
+    });
+    match (character, entity) {
+      (Some(character), Some(entity)) => {
+        decoded.push(character);
+        rest = &rest[entity.len() + 2..];
+      }
+      _ => {
+        decoded.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  decoded.push_str(rest);
+  decoded
+}
+
+// The URL in a refresh's content, like "0; url=/file.zip" or "5;'/file.zip'"
+fn refresh_url(content: &str) -> Option<&str> {
+  let (_, rest) = content.split_once([';', ','])?;
+  let rest = rest.trim();
+  let rest = match rest.get(..3) {
+    Some(prefix) if prefix.eq_ignore_ascii_case("url") => rest[3..].trim_start().strip_prefix('=')?.trim_start(),
+    _ => rest,
+  };
+  Some(rest.trim_matches(['"', '\'']).trim()).filter(|url| !url.is_empty())
+}
+
+// The string literal a script sends the page to with location = "...",
+// location.href = "...", location.replace("...") or location.assign("...")
+fn script_redirect(script: &str) -> Option<String> {
+  script.match_indices("location").find_map(|(at, _)| {
+    let mut rest = script[at + "location".len()..].trim_start();
+    if let Some(after) = rest.strip_prefix(".href") {
+      rest = after.trim_start();
+    }
+    let rest = if let Some(after) = rest.strip_prefix('=').filter(|after| !after.starts_with('=')) {
+      after
+    } else {
+      let call = rest.strip_prefix(".replace").or_else(|| rest.strip_prefix(".assign"))?;
+      call.trim_start().strip_prefix('(')?
+    };
+    string_literal(rest.trim_start())
+  })
+}
+
+// The contents of the JavaScript string literal `text` starts with
+fn string_literal(text: &str) -> Option<String> {
+  let quote = text.chars().next().filter(|quote| matches!(quote, '"' | '\''))?;
+  let mut literal = String::new();
+  let mut chars = text[1..].chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => literal.push(chars.next()?),
+      c if c == quote => return Some(literal),
+      c => literal.push(c),
+    }
+  }
+  None
+}
+
+fn has_file_extension(url: &Url) -> bool {
+  let name = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or_default();
+  name
+    .rsplit_once('.')
+    .is_some_and(|(_, extension)| FILE_EXTENSIONS.iter().any(|known| extension.eq_ignore_ascii_case(known)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_where_download_pages_lead() {
+    let base = Url::parse("https://files.example.com/get/42").unwrap();
+    let target = |html: &str| find_target(html, &base).map(String::from);
+
+    let refresh = r#"<html><head><META HTTP-EQUIV="Refresh" CONTENT="3; URL='/dl/movie.mp4?t=a&amp;b=1'">"#;
+    assert_eq!(target(refresh).as_deref(), Some("https://files.example.com/dl/movie.mp4?t=a&b=1"));
+    let script = r#"<script>if (ok) { window.location.href = "https:\/\/cdn.example.com\/song.mp3"; }</script>"#;
+    assert_eq!(target(script).as_deref(), Some("https://cdn.example.com/song.mp3"));
+    let replaced = "<script>setTimeout(function () { location.replace('file.zip') }, 5000)</script>";
+    assert_eq!(target(replaced).as_deref(), Some("https://files.example.com/get/file.zip"));
+    let marked = r#"<a href="/help">Help</a> <a class=button href=/start?id=42 download>Download</a>"#;
+    assert_eq!(target(marked).as_deref(), Some("https://files.example.com/start?id=42"));
+    let single = r#"<a href="/">Home</a><a href="/files/setup.EXE">Get it</a><a href="/files/setup.EXE">or here</a>"#;
+    assert_eq!(target(single).as_deref(), Some("https://files.example.com/files/setup.EXE"));
+
+    // A comparison isn't a redirect, and commented out markup isn't there
+    assert_eq!(target("<script>if (location == 'x') {}</script>"), None);
+    assert_eq!(target(r#"<!-- <a href="old.zip" download> -->"#), None);
+    assert_eq!(target(r#"<a href="a.zip">A</a> <a href="b.zip">B</a>"#), None);
+    assert_eq!(target(r#"<a href="javascript:start()" download>Go</a>"#), None);
+  }
+
+  #[test]
+  fn snippets_show_the_page_text() {
+    let page = "<html><head><title>Sign in</title><style>p { color: red }</style></head>\
+      <body><script>var a = '<b>';</script><p>Your session has   expired &amp; must be renewed.</p></body></html>";
+    assert_eq!(snippet(page), "Sign in Your session has expired & must be renewed.");
+    let long = snippet(&"word ".repeat(100));
+    assert!(long.ends_with('…'));
+    assert_eq!(long.chars().count(), SNIPPET_CHARS + 1);
+    assert!(is_html(Some("Text/HTML; charset=utf-8")));
+    assert!(!is_html(Some("video/mp4")));
+    assert!(wants_page("index.HTM"));
+  }
+}
@@ -0,0 +1,74 @@
+// Log lines for the steps of a download's life, all under the "download"
+// target and in one shape: "download=<id> event=<event> <details>". The id is
+// the DownloadInfo id, so filtering the log for it reconstructs what
+// happened to that download, from queued through start, retries and resumes
+// to completion or failure. The same events are kept on the download itself
+// as its timeline, for the UI to show without the log.
+use std::fmt::Arguments;
+
+use log::Level;
+use serde::{Deserialize, Serialize};
+
+use crate::DownloadInfo;
+
+const TARGET: &str = "download";
+// Events kept per download; older ones are dropped first
+pub(crate) const MAX_TIMELINE_EVENTS: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct TimelineEvent {
+  // Milliseconds since the Unix epoch
+  pub at: i64,
+  // "queued", "started", "retry", "completed" and so on
+  pub event: String,
+  // The key=value pairs of the log line, e.g. "offset=47185920"; may be empty
+  pub details: String,
+}
+
+// Logs the event and adds it to the download's timeline
+pub(crate) fn record(download: &mut DownloadInfo, level: Level, event: &str, details: Arguments<'_>) {
+  let details = details.to_string();
+  log::log!(target: TARGET, level, "{}", line(&download.id, event, &details));
+  let at = chrono::Utc::now().timestamp_millis();
+  push(&mut download.timeline, TimelineEvent { at, event: event.to_string(), details });
+}
+
+fn push(timeline: &mut Vec<TimelineEvent>, event: TimelineEvent) {
+  timeline.push(event);
+  if timeline.len() > MAX_TIMELINE_EVENTS {
+    let excess = timeline.len() - MAX_TIMELINE_EVENTS;
+    timeline.drain(..excess);
+  }
+}
+
+fn line(download_id: &str, event: &str, details: &str) -> String {
+  if details.is_empty() {
+    format!("download={} event={}", download_id, event)
+  } else {
+    format!("download={} event={} {}", download_id, event, details)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn formats_lines() {
+    assert_eq!(line("abc", "paused", ""), "download=abc event=paused");
+    assert_eq!(
+      line("abc", "retry", &format!("attempt={}/5 reason={:?}", 1, "timed out")),
+      "download=abc event=retry attempt=1/5 reason=\"timed out\""
+    );
+  }
+
+  #[test]
+  fn timeline_keeps_the_latest_events() {
+    let mut timeline = Vec::new();
+    for at in 0..MAX_TIMELINE_EVENTS as i64 + 5 {
+      push(&mut timeline, TimelineEvent { at, event: "retry".to_string(), details: String::new() });
+    }
+    assert_eq!(timeline.len(), MAX_TIMELINE_EVENTS);
+    assert_eq!(timeline.first().map(|event| event.at), Some(5));
+  }
+}
@@ -0,0 +1,37 @@
+// The std mutexes in AppState hold settings and small maps that stay
+// consistent even if a task panics midway through an update, so a poisoned
+// lock is recovered rather than failing every later command for the rest of
+// the session.
+use std::sync::{Mutex, MutexGuard};
+
+pub(crate) trait LockExt<T> {
+  fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockExt<T> for Mutex<T> {
+  fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+    self.lock().unwrap_or_else(|poisoned| {
+      log::warn!("Recovered a lock poisoned by a panicked task");
+      self.clear_poison();
+      poisoned.into_inner()
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn poisoned_locks_stay_usable() {
+    let lock = Mutex::new(1);
+    let _ = std::panic::catch_unwind(|| {
+      let _guard = lock.lock().unwrap();
+      panic!("task failed while holding the lock");
+    });
+    assert!(lock.is_poisoned());
+    *lock.lock_or_recover() += 1;
+    assert_eq!(*lock.lock_or_recover(), 2);
+    assert!(!lock.is_poisoned());
+  }
+}
@@ -0,0 +1,229 @@
+// Media details of completed video and audio files, read with ffprobe when it
+// is installed
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+// Extensions worth probing; anything else is reported as not media
+const VIDEO_EXTENSIONS: &[&str] = &[
+  "mp4", "m4v", "mkv", "webm", "mov", "avi", "wmv", "flv", "ts", "m2ts", "mpg", "mpeg", "3gp", "ogv",
+];
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "flac", "wav", "ogg", "opus", "wma"];
+
+// Folder for generated thumbnails inside the app data directory
+pub(crate) const THUMBNAILS_DIR: &str = "thumbnails";
+// Where a thumbnail is taken when the caller doesn't pick a time
+const DEFAULT_THUMBNAIL_POSITION: f64 = 0.1;
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default)]
+pub(crate) struct MediaInfo {
+  // Container, as ffprobe names it (e.g. "mov,mp4,m4a,3gp,3g2,mj2")
+  pub format: Option<String>,
+  pub duration_secs: Option<f64>,
+  // Overall bitrate in bits per second
+  pub bit_rate: Option<u64>,
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  pub video_codec: Option<String>,
+  pub audio_codec: Option<String>,
+}
+
+// Outcome of extract_media_info
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum MediaProbe {
+  Probed { info: MediaInfo, cached: bool },
+  NotMedia,
+  FfprobeUnavailable,
+}
+
+// Outcome of generate_thumbnail
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum Thumbnail {
+  Generated { path: String, cached: bool },
+  NotVideo,
+  FfmpegUnavailable,
+}
+
+fn has_extension(filename: &str, extensions: &[&str]) -> bool {
+  Path::new(filename)
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .is_some_and(|extension| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+pub(crate) fn is_media(filename: &str) -> bool {
+  is_video(filename) || has_extension(filename, AUDIO_EXTENSIONS)
+}
+
+pub(crate) fn is_video(filename: &str) -> bool {
+  has_extension(filename, VIDEO_EXTENSIONS)
+}
+
+// Seconds into the video for a thumbnail: the requested time, or 10% of the
+// duration when it is known
+pub(crate) fn thumbnail_position(at_seconds: Option<f64>, duration_secs: Option<f64>) -> f64 {
+  let position = match at_seconds {
+    Some(at) => at,
+    None => duration_secs.unwrap_or(0.0) * DEFAULT_THUMBNAIL_POSITION,
+  };
+  if position.is_finite() {
+    position.max(0.0)
+  } else {
+    0.0
+  }
+}
+
+// Saves one frame at `at_seconds` as a JPEG, scaled down to at most 640
+// pixels wide. Returns false when ffmpeg isn't installed.
+pub(crate) fn extract_frame(source: &Path, target: &Path, at_seconds: f64) -> Result<bool, String> {
+  if let Some(parent) = target.parent() {
+    std::fs::create_dir_all(parent)
+      .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+  }
+  let output = std::process::Command::new("ffmpeg")
+    .args(["-y", "-loglevel", "error", "-ss", &format!("{:.3}", at_seconds), "-i"])
+    .arg(source)
+    .args(["-frames:v", "1", "-vf", "scale='min(640,iw)':-2", "-q:v", "3"])
+    .arg(target)
+    .output();
+  let output = match output {
+    Ok(output) => output,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+    Err(e) => return Err(format!("Failed to run ffmpeg: {}", e)),
+  };
+  // Seeking past the end succeeds without writing a frame
+  let written = std::fs::metadata(target).is_ok_and(|metadata| metadata.len() > 0);
+  if !output.status.success() || !written {
+    let _ = std::fs::remove_file(target);
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    return Err(if stderr.is_empty() {
+      format!("ffmpeg produced no frame at {:.1}s", at_seconds)
+    } else {
+      format!("ffmpeg could not extract a frame: {}", stderr)
+    });
+  }
+  Ok(true)
+}
+
+// Runs ffprobe on the file. None means ffprobe isn't installed.
+pub(crate) fn probe(path: &Path) -> Result<Option<MediaInfo>, String> {
+  let output = std::process::Command::new("ffprobe")
+    .args(["-v", "error", "-print_format", "json", "-show_format", "-show_streams"])
+    .arg(path)
+    .output();
+  let output = match output {
+    Ok(output) => output,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(e) => return Err(format!("Failed to run ffprobe: {}", e)),
+  };
+  if !output.status.success() {
+    return Err(format!(
+      "ffprobe could not read {}: {}",
+      path.display(),
+      String::from_utf8_lossy(&output.stderr).trim()
+    ));
+  }
+  parse_ffprobe(&String::from_utf8_lossy(&output.stdout)).map(Some)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FfprobeOutput {
+  streams: Vec<FfprobeStream>,
+  format: FfprobeFormat,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FfprobeStream {
+  codec_type: Option<String>,
+  codec_name: Option<String>,
+  width: Option<u32>,
+  height: Option<u32>,
+  duration: Option<String>,
+}
+
+// ffprobe prints numbers in the format section as strings
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FfprobeFormat {
+  format_name: Option<String>,
+  duration: Option<String>,
+  bit_rate: Option<String>,
+}
+
+// Picks the first video and audio stream of `ffprobe -print_format json
+// -show_format -show_streams` output
+pub(crate) fn parse_ffprobe(json: &str) -> Result<MediaInfo, String> {
+  let output: FfprobeOutput =
+    serde_json::from_str(json).map_err(|e| format!("Unexpected ffprobe output: {}", e))?;
+  let stream = |kind: &str| {
+    output
+      .streams
+      .iter()
+      .find(|stream| stream.codec_type.as_deref() == Some(kind))
+  };
+  let video = stream("video");
+  let audio = stream("audio");
+  let number = |value: &Option<String>| value.as_deref().and_then(|value| value.trim().parse::<f64>().ok());
+  let duration_secs = number(&output.format.duration)
+    .or_else(|| video.and_then(|stream| number(&stream.duration)))
+    .or_else(|| audio.and_then(|stream| number(&stream.duration)));
+  Ok(MediaInfo {
+    format: output.format.format_name.clone(),
+    duration_secs,
+    bit_rate: number(&output.format.bit_rate).map(|rate| rate as u64),
+    width: video.and_then(|stream| stream.width),
+    height: video.and_then(|stream| stream.height),
+    video_codec: video.and_then(|stream| stream.codec_name.clone()),
+    audio_codec: audio.and_then(|stream| stream.codec_name.clone()),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_ffprobe_json() {
+    let json = r#"{
+      "streams": [
+        {"codec_type": "audio", "codec_name": "aac", "duration": "61.2"},
+        {"codec_type": "video", "codec_name": "h264", "width": 1920, "height": 1080}
+      ],
+      "format": {"format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "61.300000", "bit_rate": "4500123"}
+    }"#;
+    let info = parse_ffprobe(json).unwrap();
+    assert_eq!(info.duration_secs, Some(61.3));
+    assert_eq!(info.bit_rate, Some(4_500_123));
+    assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+    assert_eq!(info.video_codec.as_deref(), Some("h264"));
+    assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+
+    let audio_only = parse_ffprobe(r#"{"streams": [{"codec_type": "audio", "codec_name": "mp3"}], "format": {}}"#).unwrap();
+    assert_eq!(audio_only.width, None);
+    assert!(parse_ffprobe("not json").is_err());
+  }
+
+  #[test]
+  fn recognizes_media_extensions() {
+    assert!(is_media("clip.MP4"));
+    assert!(is_media("song.flac"));
+    assert!(!is_media("archive.zip"));
+    assert!(!is_media("README"));
+    assert!(is_video("clip.webm"));
+    assert!(!is_video("song.flac"));
+  }
+
+  #[test]
+  fn thumbnails_default_to_a_tenth_of_the_duration() {
+    assert_eq!(thumbnail_position(None, Some(120.0)), 12.0);
+    assert_eq!(thumbnail_position(Some(3.5), Some(120.0)), 3.5);
+    assert_eq!(thumbnail_position(None, None), 0.0);
+    assert_eq!(thumbnail_position(Some(-4.0), None), 0.0);
+    assert_eq!(thumbnail_position(Some(f64::NAN), None), 0.0);
+  }
+}
@@ -0,0 +1,109 @@
+// Detects metered connections and holds downloads while on one, when the user
+// opted in with set_pause_on_metered
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use tauri::Manager;
+
+use crate::{AppHandle, AppState};
+
+// How often the connection is re-checked
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+// hold_reason of downloads waiting for an unmetered connection
+pub(crate) const HOLD_REASON: &str = "metered";
+
+// Whether the active connection is metered, or None where the OS doesn't say
+#[cfg(windows)]
+pub(crate) fn detect() -> Option<bool> {
+  use windows::Networking::Connectivity::{NetworkCostType, NetworkInformation};
+
+  // No internet profile means no connection at all, which costs nothing
+  let Ok(profile) = NetworkInformation::GetInternetConnectionProfile() else {
+    return Some(false);
+  };
+  let cost = profile.GetConnectionCost().ok()?;
+  let cost_type = cost.NetworkCostType().ok()?;
+  let roaming = cost.Roaming().unwrap_or(false);
+  Some(roaming || cost_type == NetworkCostType::Fixed || cost_type == NetworkCostType::Variable)
+}
+
+// NetworkManager's global Metered property: 1 (yes) and 3 (guessed yes) count
+// as metered. Systems without NetworkManager report nothing.
+#[cfg(target_os = "linux")]
+pub(crate) fn detect() -> Option<bool> {
+  let output = std::process::Command::new("busctl")
+    .args([
+      "get-property",
+      "org.freedesktop.NetworkManager",
+      "/org/freedesktop/NetworkManager",
+      "org.freedesktop.NetworkManager",
+      "Metered",
+    ])
+    .stderr(std::process::Stdio::null())
+    .output()
+    .ok()
+    .filter(|output| output.status.success())?;
+  // Printed as "u 1"
+  let value: u32 = String::from_utf8_lossy(&output.stdout)
+    .split_whitespace()
+    .nth(1)?
+    .parse()
+    .ok()?;
+  Some(matches!(value, 1 | 3))
+}
+
+// macOS, mobile and other platforms don't expose the cost of the connection
+#[cfg(not(any(windows, target_os = "linux")))]
+pub(crate) fn detect() -> Option<bool> {
+  None
+}
+
+// True if new transfers have to wait for an unmetered connection
+pub(crate) fn holding(state: &AppState) -> bool {
+  state.pause_on_metered.load(Ordering::Relaxed) && state.on_metered.load(Ordering::Relaxed)
+}
+
+// Re-checks the connection every POLL_INTERVAL and applies changes
+pub(crate) async fn watch(app: AppHandle) {
+  let mut reported_unavailable = false;
+  loop {
+    let metered = tauri::async_runtime::spawn_blocking(detect).await.ok().flatten();
+    if metered.is_none() && !reported_unavailable {
+      log::info!("Metered connection status is unavailable here; treating the connection as unmetered");
+      reported_unavailable = true;
+    }
+    let metered = metered.unwrap_or(false);
+    let state = app.state::<AppState>();
+    if state.on_metered.swap(metered, Ordering::Relaxed) != metered {
+      log::info!("Connection is now {}", if metered { "metered" } else { "unmetered" });
+      apply(&app).await;
+    }
+    tokio::time::sleep(POLL_INTERVAL).await;
+  }
+}
+
+// Holds or releases downloads to match the current connection and setting
+pub(crate) async fn apply(app: &AppHandle) {
+  if holding(&app.state::<AppState>()) {
+    hold_active(app).await;
+  } else {
+    release(app).await;
+  }
+}
+
+// Sends running and waiting downloads back to "queued" until the connection
+// is unmetered again
+async fn hold_active(app: &AppHandle) {
+  let held = crate::hold::hold_active(app, HOLD_REASON, "queued").await;
+  if held > 0 {
+    log::info!("Holding {} downloads while on a metered connection", held);
+  }
+}
+
+// Restarts the downloads held for the metered connection
+async fn release(app: &AppHandle) {
+  let released = crate::hold::release(app, HOLD_REASON, &["queued"]).await;
+  if released > 0 {
+    log::info!("Resuming {} downloads held for the metered connection", released);
+  }
+}
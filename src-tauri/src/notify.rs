@@ -0,0 +1,42 @@
+use tauri::Manager;
+use tauri_plugin_notification::NotificationExt;
+
+use crate::{AppHandle, AppState};
+
+// Desktop notifications for finished downloads, sent only when the user opted
+// in with set_notifications_enabled. Desktop notifications carry no click
+// callback, so clicking one just activates the app the way the OS does by
+// default.
+pub(crate) fn download_completed(app: &AppHandle, filename: &str, bytes: u64) {
+  if app.state::<AppState>().notify_on_complete.load(std::sync::atomic::Ordering::Relaxed) {
+    show(app, "Download finished", &format!("{} finished — {}", filename, format_size(bytes)));
+  }
+}
+
+pub(crate) fn download_failed(app: &AppHandle, filename: &str, error: &str) {
+  if app.state::<AppState>().notify_on_error.load(std::sync::atomic::Ordering::Relaxed) {
+    show(app, "Download failed", &format!("{} failed: {}", filename, error));
+  }
+}
+
+fn show(app: &AppHandle, title: &str, body: &str) {
+  if let Err(e) = app.notification().builder().title(title).body(body).show() {
+    log::warn!("Failed to show notification: {}", e);
+  }
+}
+
+// Human readable size such as "1.2 GB"
+pub(crate) fn format_size(bytes: u64) -> String {
+  const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+  let mut size = bytes as f64;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{} B", bytes)
+  } else {
+    format!("{:.1} {}", size, UNITS[unit])
+  }
+}
@@ -0,0 +1,114 @@
+// Offline mode, turned on with set_offline_mode when the user knows there is
+// no network: downloads are accepted but wait as "queued" with hold_reason
+// "offline" instead of burning their retries, and running ones are paused.
+// Everything held resumes when offline mode is turned off, or on its own once
+// the OS reports the connection back after having seen it down.
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use tauri::Manager;
+
+use crate::{AppHandle, AppState};
+
+// How often connectivity is checked while offline mode is on
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+// hold_reason of downloads waiting for the network
+pub(crate) const HOLD_REASON: &str = "offline";
+
+// Whether the OS has a connection to the internet, or None where it doesn't say
+#[cfg(windows)]
+pub(crate) fn detect() -> Option<bool> {
+  use windows::Networking::Connectivity::{NetworkConnectivityLevel, NetworkInformation};
+
+  let Ok(profile) = NetworkInformation::GetInternetConnectionProfile() else {
+    return Some(false);
+  };
+  let level = profile.GetNetworkConnectivityLevel().ok()?;
+  Some(level == NetworkConnectivityLevel::InternetAccess)
+}
+
+// NetworkManager's global Connectivity property: 4 is full connectivity, 1
+// to 3 are none, a captive portal and limited; 0 means it doesn't know
+#[cfg(target_os = "linux")]
+pub(crate) fn detect() -> Option<bool> {
+  let output = std::process::Command::new("busctl")
+    .args([
+      "get-property",
+      "org.freedesktop.NetworkManager",
+      "/org/freedesktop/NetworkManager",
+      "org.freedesktop.NetworkManager",
+      "Connectivity",
+    ])
+    .stderr(std::process::Stdio::null())
+    .output()
+    .ok()
+    .filter(|output| output.status.success())?;
+  // Printed as "u 4"
+  let value: u32 = String::from_utf8_lossy(&output.stdout)
+    .split_whitespace()
+    .nth(1)?
+    .parse()
+    .ok()?;
+  (value != 0).then_some(value == 4)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub(crate) fn detect() -> Option<bool> {
+  None
+}
+
+// True if new transfers have to wait for offline mode to end
+pub(crate) fn holding(state: &AppState) -> bool {
+  state.offline_mode.load(Ordering::Relaxed)
+}
+
+// While offline mode is on, turns it off once the connection comes back.
+// Only a return counts: offline mode turned on while still connected stays on
+// until the connection has been down at least once.
+pub(crate) async fn watch(app: AppHandle) {
+  let mut seen_down = false;
+  loop {
+    tokio::time::sleep(POLL_INTERVAL).await;
+    if !holding(&app.state::<AppState>()) {
+      seen_down = false;
+      continue;
+    }
+    match tauri::async_runtime::spawn_blocking(detect).await.ok().flatten() {
+      Some(false) => seen_down = true,
+      Some(true) if seen_down => {
+        seen_down = false;
+        if app.state::<AppState>().offline_mode.swap(false, Ordering::Relaxed) {
+          log::info!("Network connection is back; offline mode disabled");
+          release(&app).await;
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+// Holds or releases downloads to match the setting
+pub(crate) async fn apply(app: &AppHandle) {
+  if holding(&app.state::<AppState>()) {
+    hold_active(app).await;
+  } else {
+    release(app).await;
+  }
+}
+
+// Pauses everything running or waiting, retries included, and waits for the
+// tasks to close their files
+async fn hold_active(app: &AppHandle) {
+  let held = crate::hold::hold_active(app, HOLD_REASON, "paused").await;
+  if held > 0 {
+    log::info!("Paused {} downloads for offline mode", held);
+  }
+}
+
+// Restarts the downloads paused or queued while offline
+async fn release(app: &AppHandle) {
+  let released = crate::hold::release(app, HOLD_REASON, &["paused", "queued"]).await;
+  if released > 0 {
+    log::info!("Resuming {} downloads held for offline mode", released);
+  }
+}
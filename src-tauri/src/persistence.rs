@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, DownloadInfo, LogLevel};
+
+// File name of the persisted queue inside the app data directory
+pub(crate) const STATE_FILE: &str = "downloads.json";
+// User preferences, kept next to the queue
+pub(crate) const SETTINGS_FILE: &str = "settings.json";
+// Format version written by export_queue. Older documents are upgraded on
+// import; newer ones are refused.
+pub(crate) const QUEUE_EXPORT_VERSION: u64 = 1;
+
+// Document produced by export_queue and read by import_queue
+#[derive(Serialize, Deserialize)]
+pub(crate) struct QueueExport {
+  pub version: u64,
+  // Milliseconds since the Unix epoch
+  pub exported_at: i64,
+  pub downloads: Vec<DownloadInfo>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct Settings {
+  // Chosen with set_download_directory; None uses the platform default
+  pub download_dir: Option<PathBuf>,
+  // Chosen with set_temp_directory; None writes downloads in place
+  pub temp_dir: Option<PathBuf>,
+  // Chosen with set_log_level
+  pub log_level: LogLevel,
+}
+
+// Reads the settings file, falling back to defaults when it is missing or
+// unreadable
+pub(crate) fn load_settings(path: &Path) -> Settings {
+  match std::fs::read_to_string(path) {
+    Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+      log::warn!("Ignoring corrupt settings {}: {}", path.display(), e);
+      Settings::default()
+    }),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+    Err(e) => {
+      log::warn!("Failed to read {}: {}; using default settings", path.display(), e);
+      Settings::default()
+    }
+  }
+}
+
+pub(crate) fn save_settings(path: &Path, settings: &Settings) -> Result<(), String> {
+  let json = serde_json::to_string_pretty(settings)
+    .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+  write_atomic(path, json.as_bytes())
+    .map_err(|e| format!("Failed to save settings to {}: {}", path.display(), e))
+}
+
+// Loads the persisted queue. A missing or unreadable file yields an empty queue
+// rather than failing startup. Downloads that were in flight when the app quit
+// come back as "paused" so the user can resume them, from their partial file
+// when it survived (see reconcile_partial).
+pub(crate) fn load(path: &Path) -> HashMap<String, DownloadInfo> {
+  let contents = match std::fs::read_to_string(path) {
+    Ok(contents) => contents,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return HashMap::new(),
+    Err(e) => {
+      log::warn!("Failed to read {}: {}; starting with an empty queue", path.display(), e);
+      return HashMap::new();
+    }
+  };
+  let mut downloads: HashMap<String, DownloadInfo> = match serde_json::from_str(&contents) {
+    Ok(downloads) => downloads,
+    Err(e) => {
+      log::warn!("Ignoring corrupt download state {}: {}", path.display(), e);
+      return HashMap::new();
+    }
+  };
+
+  for download in downloads.values_mut() {
+    if download.is_in_progress() {
+      download.status = "paused".to_string();
+      reconcile_partial(download);
+    }
+    // State files from before credentials were kept out of them
+    download.options.keep_secrets_apart();
+    // State files from before URLs were normalized
+    if download.normalized_url.is_empty() {
+      download.normalized_url = crate::normalize_url(&download.url);
+    }
+    // State files from before reordering queue everything by age
+    if download.queue_position == 0 {
+      download.queue_position = download.created_at;
+    }
+    // An interrupted move leaves the original in place until the copy is done
+    if download.status == "moving" {
+      download.status = "completed".to_string();
+    }
+  }
+  log::info!("Restored {} downloads from {}", downloads.len(), path.display());
+  downloads
+}
+
+// Matches the recorded progress of a download interrupted mid-transfer to the
+// partial file it left. Progress is checkpointed periodically while the data
+// keeps being written, so the file is normally a little ahead and wins.
+// Segmented downloads preallocate their file and keep their own record, and a
+// HLS download counts segments, so only their file's presence is checked. A
+// partial file larger than the whole file can't be resumed and is discarded.
+pub(crate) fn reconcile_partial(download: &mut DownloadInfo) {
+  let size = std::fs::metadata(download.working_path())
+    .ok()
+    .filter(|metadata| metadata.is_file())
+    .map(|metadata| metadata.len());
+  download.partial_kept = size.is_some();
+  let Some(size) = size else {
+    download.bytes_downloaded = 0;
+    download.progress = 0.0;
+    download.hls = None;
+    download.segments = None;
+    return;
+  };
+  if download.segments.is_some() || download.hls.is_some() || download.bytes_downloaded == size {
+    return;
+  }
+  if download.total_bytes.is_some_and(|total| size > total) {
+    log::warn!(
+      "Partial file of {} is larger than the whole file ({} > {} bytes); it will start over",
+      download.id,
+      size,
+      download.total_bytes.unwrap_or_default()
+    );
+    let _ = std::fs::remove_file(download.working_path());
+    download.partial_kept = false;
+    download.bytes_downloaded = 0;
+    download.progress = 0.0;
+    return;
+  }
+  log::info!(
+    "Partial file of {} has {} bytes, {} were recorded; resuming from the file",
+    download.id,
+    size,
+    download.bytes_downloaded
+  );
+  download.bytes_downloaded = size;
+  if let Some(total) = download.total_bytes.filter(|total| *total > 0) {
+    download.progress = size as f64 / total as f64 * 100.0;
+  }
+}
+
+// Writes the current queue to disk. The snapshot is written to a temporary file
+// and renamed over the old one so a crash never leaves a half-written state.
+// Only the serialization holds the downloads lock; the file is written on the
+// blocking pool.
+pub(crate) async fn save(state: &AppState) {
+  // Serializes writers so an older snapshot can't overwrite a newer one
+  let _guard = state.persist_lock.lock().await;
+  let json = serde_json::to_string_pretty(&*state.downloads.lock().await);
+  let json = match json {
+    Ok(json) => json,
+    Err(e) => {
+      log::error!("Failed to serialize download state: {}", e);
+      return;
+    }
+  };
+
+  let path = state.state_file.clone();
+  let written = tauri::async_runtime::spawn_blocking(move || write_atomic(&path, json.as_bytes())).await;
+  match written {
+    Ok(Ok(())) => {}
+    Ok(Err(e)) => log::error!("Failed to save download state to {}: {}", state.state_file.display(), e),
+    Err(e) => log::error!("Saving download state failed: {}", e),
+  }
+}
+
+pub(crate) fn export_queue(downloads: Vec<DownloadInfo>) -> Result<String, String> {
+  let export = QueueExport {
+    version: QUEUE_EXPORT_VERSION,
+    exported_at: chrono::Utc::now().timestamp_millis(),
+    downloads,
+  };
+  serde_json::to_string_pretty(&export).map_err(|e| format!("Failed to serialize the queue: {}", e))
+}
+
+pub(crate) async fn write_export(path: PathBuf, json: String) -> Result<(), String> {
+  let display = path.display().to_string();
+  tauri::async_runtime::spawn_blocking(move || write_atomic(&path, json.as_bytes()))
+    .await
+    .map_err(|e| format!("Writing {} failed: {}", display, e))?
+    .map_err(|e| format!("Failed to write {}: {}", display, e))
+}
+
+// Parses an exported queue. The version is read first so each older format
+// can be migrated to the current one before it is deserialized.
+pub(crate) fn parse_queue_export(json: &str) -> Result<QueueExport, String> {
+  let value: serde_json::Value =
+    serde_json::from_str(json).map_err(|e| format!("Not a queue export: {}", e))?;
+  let version = value
+    .get("version")
+    .and_then(serde_json::Value::as_u64)
+    .ok_or("Queue export has no version")?;
+  if version > QUEUE_EXPORT_VERSION {
+    return Err(format!(
+      "Queue export version {} is newer than this app supports ({})",
+      version, QUEUE_EXPORT_VERSION
+    ));
+  }
+  if version == 0 {
+    return Err("Queue export version 0 is not valid".to_string());
+  }
+  // Version 1 is the current format, so there is nothing to migrate yet
+  serde_json::from_value(value).map_err(|e| format!("Invalid queue export: {}", e))
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let tmp = path.with_extension("json.tmp");
+  let mut file = std::fs::File::create(&tmp)?;
+  file.write_all(contents)?;
+  // On disk before the rename, so a crash can't leave an empty file in its place
+  file.sync_all()?;
+  std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn interrupted_downloads_resume_from_their_partial_file() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-reconcile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.bin"), [0u8; 60]).unwrap();
+    let mut download = DownloadInfo {
+      id: "a".to_string(),
+      path: dir.join("a.bin"),
+      bytes_downloaded: 40,
+      total_bytes: Some(100),
+      etag: Some("\"v1\"".to_string()),
+      ..Default::default()
+    };
+    reconcile_partial(&mut download);
+    assert!(download.partial_kept);
+    assert_eq!(download.bytes_downloaded, 60);
+    assert_eq!(download.progress, 60.0);
+    assert_eq!(download.etag.as_deref(), Some("\"v1\""));
+
+    // Larger than the file it is part of
+    download.total_bytes = Some(50);
+    reconcile_partial(&mut download);
+    assert!(!download.partial_kept);
+    assert!(!dir.join("a.bin").exists());
+
+    download.bytes_downloaded = 30;
+    reconcile_partial(&mut download);
+    assert_eq!((download.partial_kept, download.bytes_downloaded), (false, 0));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn queue_export_round_trips_and_checks_the_version() {
+    let download = DownloadInfo { id: "a".to_string(), ..Default::default() };
+    let json = export_queue(vec![download]).unwrap();
+    let parsed = parse_queue_export(&json).unwrap();
+    assert_eq!(parsed.version, QUEUE_EXPORT_VERSION);
+    assert_eq!(parsed.downloads[0].id, "a");
+
+    assert!(parse_queue_export(r#"{"downloads": []}"#).is_err());
+    assert!(parse_queue_export(r#"{"version": 99, "downloads": []}"#).is_err());
+    assert!(parse_queue_export("[]").is_err());
+  }
+}
@@ -0,0 +1,58 @@
+// Expiry of presigned URLs (S3 and compatible stores, Google Cloud Storage,
+// CloudFront), read from their query parameters. A download whose link has
+// lapsed fails with a clear error before a retry or resume sends it, instead
+// of with whatever 403 the server answers; the fix is a fresh link, so it
+// isn't retried. URLs without these parameters are never affected.
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+// When the URL's signature stops being accepted, if it says
+pub(crate) fn expiry(url: &url::Url) -> Option<DateTime<Utc>> {
+  let param = |name: &str| {
+    url
+      .query_pairs()
+      .find(|(key, _)| key.eq_ignore_ascii_case(name))
+      .map(|(_, value)| value.into_owned())
+  };
+  // SigV4-style: signing time plus a lifetime in seconds
+  for (date, expires) in [("X-Amz-Date", "X-Amz-Expires"), ("X-Goog-Date", "X-Goog-Expires")] {
+    if let (Some(date), Some(expires)) = (param(date), param(expires)) {
+      let signed = NaiveDateTime::parse_from_str(&date, "%Y%m%dT%H%M%SZ").ok()?.and_utc();
+      let lifetime: i64 = expires.parse().ok()?;
+      return signed.checked_add_signed(chrono::Duration::seconds(lifetime));
+    }
+  }
+  // SigV2 and CloudFront: an absolute Unix timestamp
+  param("Expires")?.parse().ok().and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+// Fails once a presigned URL has expired
+pub(crate) fn check(url: &str, now: DateTime<Utc>) -> Result<(), String> {
+  let expired_at = url::Url::parse(url).ok().as_ref().and_then(expiry).filter(|expiry| *expiry <= now);
+  match expired_at {
+    Some(expired_at) => Err(format!(
+      "Presigned URL expired at {}; get a fresh link and download it again",
+      expired_at.format("%Y-%m-%d %H:%M:%S UTC")
+    )),
+    None => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_expiry_from_signed_queries() {
+    let at = |secs| DateTime::from_timestamp(secs, 0).unwrap();
+    let s3 = "https://bucket.s3.amazonaws.com/a.bin?X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Date=20240309T120000Z&X-Amz-Expires=3600&X-Amz-Signature=abc";
+    let expires = expiry(&url::Url::parse(s3).unwrap()).unwrap();
+    assert_eq!(expires.to_rfc3339(), "2024-03-09T13:00:00+00:00");
+    assert!(check(s3, expires - chrono::Duration::seconds(1)).is_ok());
+    assert!(check(s3, expires).unwrap_err().starts_with("Presigned URL expired at 2024-03-09 13:00:00 UTC"));
+
+    let cloudfront = "https://d111.cloudfront.net/v.mp4?Expires=1710000000&Signature=x&Key-Pair-Id=K";
+    assert!(check(cloudfront, at(1710000001)).is_err());
+    assert!(check("https://example.com/a.bin?expires=soon", at(i32::MAX.into())).is_ok());
+    assert!(check("https://example.com/a.bin", at(i32::MAX.into())).is_ok());
+  }
+}
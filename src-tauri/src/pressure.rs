@@ -0,0 +1,265 @@
+// Adaptive throttling, turned on with set_adaptive_throttle. The CPU and the
+// network are sampled every SAMPLE_INTERVAL; while the CPU is busy or other
+// programs are receiving a lot of traffic next to the downloads, the global
+// bandwidth cap is halved, down to MIN_CAP. Once the system has been calm
+// for a few samples the cap grows again until it is back at the limit set
+// with set_bandwidth_limit, or no longer holds the downloads back.
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sysinfo::{Networks, System};
+use tauri::Manager;
+
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+// Share of CPU time busy across all cores that counts as load
+const CPU_BUSY: f64 = 0.85;
+// Bytes per second received by other programs that counts as load
+const OTHER_TRAFFIC: u64 = 512 * 1024;
+// The cap is never lowered below this
+const MIN_CAP: u64 = 256 * 1024;
+// Calm samples in a row before the cap is raised
+const CALM_SAMPLES: u32 = 3;
+
+// What the OS reports at one sample
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Counters {
+  // Busy share of CPU time since the previous sample, 0 to 1
+  cpu: f64,
+  // Bytes received by all non-loopback interfaces since they came up; None
+  // where not available
+  received: Option<u64>,
+}
+
+// What the latest two samples say about the system
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Load {
+  // Busy share of CPU time, 0 to 1
+  cpu: f64,
+  // Bytes per second received by everything but the downloads
+  other_traffic: Option<u64>,
+}
+
+impl Load {
+  // Compares two samples taken `elapsed` apart while the downloads received
+  // `downloaded` bytes per second
+  pub(crate) fn between(before: Counters, after: Counters, elapsed: Duration, downloaded: u64) -> Self {
+    let cpu = after.cpu.clamp(0.0, 1.0);
+    let seconds = elapsed.as_secs_f64().max(0.001);
+    let other_traffic = before.received.zip(after.received).map(|(before, after)| {
+      let rate = (after.saturating_sub(before) as f64 / seconds) as u64;
+      rate.saturating_sub(downloaded)
+    });
+    Self { cpu, other_traffic }
+  }
+
+  fn is_high(&self) -> bool {
+    self.cpu >= CPU_BUSY || self.other_traffic.is_some_and(|traffic| traffic >= OTHER_TRAFFIC)
+  }
+}
+
+// The cap adaptive throttling currently imposes
+#[derive(Default)]
+pub(crate) struct Adaptive {
+  // None while the system is calm and the downloads run at the user's limit
+  cap: Option<u64>,
+  calm: u32,
+  load: Option<Load>,
+}
+
+impl Adaptive {
+  // Takes the latest load and the downloads' combined speed, and returns the
+  // new cap. A cap that is back at the user's limit, or more than twice what
+  // the downloads are using, is dropped.
+  pub(crate) fn update(&mut self, load: Load, throughput: u64, limit: Option<u64>) -> Option<u64> {
+    self.load = Some(load);
+    if load.is_high() && throughput > 0 {
+      self.calm = 0;
+      let current = self.cap.or(limit).unwrap_or(throughput).min(throughput.max(MIN_CAP));
+      self.cap = Some((current / 2).max(MIN_CAP));
+    } else if let Some(cap) = self.cap {
+      self.calm += 1;
+      if self.calm >= CALM_SAMPLES {
+        let raised = cap.saturating_add(cap / 2);
+        let released = limit.is_some_and(|limit| raised >= limit) || raised > throughput.saturating_mul(2);
+        self.cap = (!released).then_some(raised);
+      }
+    }
+    self.cap
+  }
+
+  fn reset(&mut self) {
+    *self = Self::default();
+  }
+}
+
+// Reads the counters through sysinfo. Kept from one sample to the next, as
+// CPU usage is measured between two refreshes.
+struct Sampler {
+  system: System,
+  networks: Networks,
+}
+
+impl Sampler {
+  fn new() -> Self {
+    Self { system: System::new(), networks: Networks::new_with_refreshed_list() }
+  }
+
+  // None where sysinfo doesn't support the platform
+  fn read(&mut self) -> Option<Counters> {
+    if !sysinfo::IS_SUPPORTED_SYSTEM {
+      return None;
+    }
+    self.system.refresh_cpu_usage();
+    self.networks.refresh(true);
+    let cpu = f64::from(self.system.global_cpu_usage()) / 100.0;
+    let interfaces: Vec<_> = self
+      .networks
+      .list()
+      .values()
+      .filter(|data| !data.ip_networks().iter().any(|network| network.addr.is_loopback()))
+      .collect();
+    let received = (!interfaces.is_empty()).then(|| interfaces.iter().map(|data| data.total_received()).sum());
+    Some(Counters { cpu, received })
+  }
+}
+
+// Sets the global throttle to the stricter of the user's limit and the
+// adaptive cap, and shows the result on every download
+pub(crate) async fn apply(state: &AppState) {
+  let limit = Some(state.bandwidth_limit.load(Ordering::Relaxed)).filter(|limit| *limit > 0);
+  let cap = state.adaptive.lock_or_recover().cap;
+  let rate = crate::effective_speed_limit(limit, cap);
+  if state.throttle.rate() == rate {
+    return;
+  }
+  state.throttle.set_rate(rate);
+  for download in state.downloads.lock().await.values_mut() {
+    download.effective_speed_limit = crate::effective_speed_limit(download.speed_limit, rate);
+  }
+}
+
+// Samples the system while adaptive throttling is on and moves the cap
+pub(crate) async fn watch(app: AppHandle) {
+  let mut previous: Option<(Counters, Instant)> = None;
+  let mut sampler = Sampler::new();
+  let mut reported_unavailable = false;
+  loop {
+    tokio::time::sleep(SAMPLE_INTERVAL).await;
+    let state = app.state::<AppState>();
+    if !state.adaptive_throttle.load(Ordering::Relaxed) {
+      previous = None;
+      continue;
+    }
+    let (counters, returned) = tauri::async_runtime::spawn_blocking(move || (sampler.read(), sampler))
+      .await
+      .unwrap_or_else(|_| (None, Sampler::new()));
+    sampler = returned;
+    let Some(counters) = counters else {
+      if !reported_unavailable {
+        log::info!("System load can't be read here; adaptive throttling has no effect");
+        reported_unavailable = true;
+      }
+      continue;
+    };
+    let now = Instant::now();
+    let Some((before, at)) = previous.replace((counters, now)) else { continue };
+    let throughput = state
+      .downloads
+      .lock()
+      .await
+      .values()
+      .filter(|download| download.is_in_progress())
+      .map(|download| download.speed_bytes_per_sec as u64)
+      .sum();
+    let load = Load::between(before, counters, now - at, throughput);
+    let limit = Some(state.bandwidth_limit.load(Ordering::Relaxed)).filter(|limit| *limit > 0);
+    let (old, new) = {
+      let mut adaptive = state.adaptive.lock_or_recover();
+      let old = adaptive.cap;
+      (old, adaptive.update(load, throughput, limit))
+    };
+    if old != new {
+      match new {
+        Some(cap) => {
+          log::info!("System under load (cpu {:.0}%); bandwidth capped at {} bytes/s", load.cpu * 100.0, cap)
+        }
+        None => log::info!("System calm again; adaptive bandwidth cap lifted"),
+      }
+      apply(&state).await;
+    }
+  }
+}
+
+// Turns adaptive throttling on or off; turning it off lifts its cap at once
+pub(crate) async fn set_enabled(state: &AppState, enabled: bool) {
+  state.adaptive_throttle.store(enabled, Ordering::Relaxed);
+  if !enabled {
+    state.adaptive.lock_or_recover().reset();
+    apply(state).await;
+  }
+}
+
+#[derive(Serialize)]
+pub(crate) struct ThrottleStatus {
+  adaptive: bool,
+  // Set with set_bandwidth_limit
+  limit: Option<u64>,
+  // What downloads are held to right now: the limit, or lower while
+  // throttled_for_load
+  effective_limit: Option<u64>,
+  throttled_for_load: bool,
+  // The latest sample; None until adaptive throttling has taken two
+  load: Option<Load>,
+}
+
+pub(crate) fn status(state: &AppState) -> ThrottleStatus {
+  let adaptive = state.adaptive.lock_or_recover();
+  ThrottleStatus {
+    adaptive: state.adaptive_throttle.load(Ordering::Relaxed),
+    limit: Some(state.bandwidth_limit.load(Ordering::Relaxed)).filter(|limit| *limit > 0),
+    effective_limit: state.throttle.rate(),
+    throttled_for_load: adaptive.cap.is_some(),
+    load: adaptive.load,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cap_halves_under_load_and_recovers_when_calm() {
+    const MIB: u64 = 1024 * 1024;
+    let busy = Load { cpu: 0.95, other_traffic: None };
+    let calm = Load { cpu: 0.2, other_traffic: Some(0) };
+    let mut adaptive = Adaptive::default();
+    assert_eq!(adaptive.update(calm, 8 * MIB, None), None);
+    assert_eq!(adaptive.update(busy, 8 * MIB, None), Some(4 * MIB));
+    assert_eq!(adaptive.update(busy, 4 * MIB, None), Some(2 * MIB));
+    // Other programs' traffic counts as load too
+    let traffic = Load { cpu: 0.1, other_traffic: Some(MIB) };
+    assert_eq!(adaptive.update(traffic, 2 * MIB, None), Some(MIB));
+    assert_eq!(adaptive.update(busy, MIB / 8, None), Some(MIN_CAP));
+    // Raised only after CALM_SAMPLES calm samples, and dropped at the limit
+    assert_eq!(adaptive.update(calm, MIN_CAP, Some(MIB)), Some(MIN_CAP));
+    assert_eq!(adaptive.update(calm, MIN_CAP, Some(MIB)), Some(MIN_CAP));
+    assert_eq!(adaptive.update(calm, MIN_CAP, Some(MIB)), Some(MIN_CAP * 3 / 2));
+    assert_eq!(adaptive.update(calm, MIN_CAP * 3 / 2, Some(MIB)), Some(MIN_CAP * 9 / 4));
+    assert_eq!(adaptive.update(calm, MIN_CAP * 9 / 4, Some(MIB)), Some(MIN_CAP * 27 / 8));
+    assert_eq!(adaptive.update(calm, MIN_CAP * 27 / 8, Some(MIB)), None);
+  }
+
+  #[test]
+  fn load_subtracts_the_downloads_own_traffic() {
+    let before = Counters { cpu: 0.9, received: Some(0) };
+    let after = Counters { cpu: 0.5, received: Some(6 * 1024 * 1024) };
+    let load = Load::between(before, after, Duration::from_secs(2), 2 * 1024 * 1024);
+    assert_eq!(load, Load { cpu: 0.5, other_traffic: Some(1024 * 1024) });
+    let unknown = Counters { received: None, ..after };
+    assert_eq!(Load::between(before, unknown, Duration::from_secs(2), 0).other_traffic, None);
+  }
+}
@@ -0,0 +1,81 @@
+// Optional cap on the space downloads may take up, set with set_storage_quota.
+// A download that would push the downloads directory past it waits as
+// "queued" with hold_reason "quota" until space is freed or the cap raised.
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+use tauri::Manager;
+
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState};
+
+// hold_reason of downloads waiting for quota
+pub(crate) const HOLD_REASON: &str = "quota";
+
+// The configured quota in bytes; zero in AppState means none
+pub(crate) fn limit(state: &AppState) -> Option<u64> {
+  Some(state.storage_quota.load(Ordering::Relaxed)).filter(|quota| *quota > 0)
+}
+
+// Checks whether `incoming` more bytes fit under the quota, counting the files
+// already in the downloads directory and what other running downloads still
+// expect to write. With an unknown size the download may start as long as the
+// quota isn't already used up. Returns false after holding the download.
+pub(crate) async fn admit(app: &AppHandle, download_id: &str, incoming: Option<u64>) -> Result<bool, String> {
+  let state = app.state::<AppState>();
+  let Some(quota) = limit(&state) else {
+    return Ok(true);
+  };
+  let download_dir: PathBuf = state
+    .download_dir
+    .lock_or_recover()
+    .clone();
+  let used = tauri::async_runtime::spawn_blocking(move || crate::storage::used_space(&download_dir))
+    .await
+    .map_err(|e| format!("Measuring the downloads directory failed: {}", e))??;
+  let pending: u64 = state
+    .downloads
+    .lock()
+    .await
+    .values()
+    .filter(|download| download.id != download_id && download.status == "downloading")
+    .filter_map(|download| download.total_bytes.map(|total| total.saturating_sub(download.bytes_downloaded)))
+    .sum();
+  let needed = used + pending + incoming.unwrap_or(0);
+  let fits = match incoming {
+    Some(_) => needed <= quota,
+    None => needed < quota,
+  };
+  if fits {
+    return Ok(true);
+  }
+
+  crate::downloader::update_download(app, download_id, |download| {
+    download.status = "queued".to_string();
+    download.hold_reason = Some(HOLD_REASON.to_string());
+  })
+  .await;
+  log::info!(
+    "Download {} held: {} bytes used or pending plus {} incoming exceed the {} byte quota",
+    download_id,
+    used + pending,
+    incoming.map_or_else(|| "unknown".to_string(), |incoming| incoming.to_string()),
+    quota
+  );
+  Ok(false)
+}
+
+// Starts the downloads held for quota again, oldest first, after space was
+// freed or the quota changed. Each one checks the quota anew when it starts.
+pub(crate) async fn release(app: &AppHandle) {
+  let released = crate::hold::release(app, HOLD_REASON, &["queued"]).await;
+  if released > 0 {
+    log::info!("Retrying {} downloads held for quota", released);
+  }
+}
+
+// Downloads held for quota survive clear_storage so they can proceed once the
+// space is free
+pub(crate) fn is_held(download: &crate::DownloadInfo) -> bool {
+  download.hold_reason.as_deref() == Some(HOLD_REASON)
+}
@@ -0,0 +1,223 @@
+// Rebuilds queue entries from the downloads directory, for a lost state file
+// or files dropped into the folder by hand. Sidecars written next to the files
+// supply the original URL, hash and timestamps when they exist.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::sidecar::{self, Sidecar};
+use crate::DownloadInfo;
+
+#[derive(Serialize, Default, PartialEq, Debug)]
+pub(crate) struct ReindexSummary {
+  pub added: usize,
+  pub updated: usize,
+}
+
+// A file found in the downloads directory
+pub(crate) struct Found {
+  path: PathBuf,
+  size: u64,
+  // Modification time in milliseconds since the Unix epoch
+  modified: i64,
+  sidecar: Option<Sidecar>,
+  // The folder it was found in, relative to the downloads directory
+  subdir: Option<String>,
+}
+
+// Lists the files in the downloads directory and its subdirectories, down to
+// filename::MAX_SUBDIR_DEPTH, with their sidecars. Hidden files and folders,
+// symlinks and the sidecars themselves are skipped, and an unreadable sidecar
+// or subdirectory is ignored rather than failing the scan.
+pub(crate) fn scan(download_dir: &Path) -> Result<Vec<Found>, String> {
+  let mut found = Vec::new();
+  match scan_dir(download_dir, &[], &mut found) {
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+    Err(e) => Err(format!("Failed to read {}: {}", download_dir.display(), e)),
+    Ok(()) => Ok(found),
+  }
+}
+
+fn scan_dir(dir: &Path, subdir: &[String], found: &mut Vec<Found>) -> std::io::Result<()> {
+  for entry in std::fs::read_dir(dir)? {
+    let entry = entry?;
+    let path = entry.path();
+    let name = entry.file_name().to_string_lossy().into_owned();
+    // Not followed: a symlink could point anywhere outside the directory
+    let Ok(metadata) = std::fs::symlink_metadata(&path) else { continue };
+    if metadata.file_type().is_symlink() {
+      log::info!("Not indexing symlink {}", path.display());
+      continue;
+    }
+    if name.starts_with('.') {
+      continue;
+    }
+    if metadata.is_dir() {
+      if subdir.len() < crate::filename::MAX_SUBDIR_DEPTH {
+        let nested = [subdir, &[name]].concat();
+        if let Err(e) = scan_dir(&path, &nested, found) {
+          log::warn!("Skipping {} while reindexing: {}", path.display(), e);
+        }
+      }
+      continue;
+    }
+    if !metadata.is_file() || sidecar::is_sidecar(&path) {
+      continue;
+    }
+    let modified = metadata
+      .modified()
+      .ok()
+      .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+      .map_or(0, |since| since.as_millis() as i64);
+    let sidecar_path = sidecar::path_for(&path);
+    let sidecar = if std::fs::symlink_metadata(&sidecar_path).is_ok_and(|metadata| metadata.is_file()) {
+      sidecar::read(&sidecar_path)
+        .map_err(|e| log::warn!("Ignoring sidecar while reindexing: {}", e))
+        .ok()
+    } else {
+      None
+    };
+    let subdir = (!subdir.is_empty()).then(|| subdir.join("/"));
+    found.push(Found { path, size: metadata.len(), modified, sidecar, subdir });
+  }
+  Ok(())
+}
+
+// Adds an entry for every found file no download points at, and brings back
+// "missing" downloads whose file is there again. Returns the ids added.
+pub(crate) fn merge(
+  downloads: &mut HashMap<String, DownloadInfo>,
+  found: Vec<Found>,
+) -> (ReindexSummary, Vec<String>) {
+  let mut summary = ReindexSummary::default();
+  let mut added = Vec::new();
+  for file in found {
+    let tracked = downloads.values_mut().find(|download| download.path == file.path);
+    if let Some(download) = tracked {
+      if download.status == "missing" && download.bytes_downloaded == file.size {
+        download.status = "completed".to_string();
+        summary.updated += 1;
+      }
+      continue;
+    }
+    let download = from_file(downloads, file);
+    added.push(download.id.clone());
+    downloads.insert(download.id.clone(), download);
+    summary.added += 1;
+  }
+  (summary, added)
+}
+
+// A completed download for an untracked file. Without a sidecar the URL is
+// the file's own file:// URL and the queue time its modification time. A file
+// whose size differs from its sidecar is marked "corrupt".
+fn from_file(downloads: &HashMap<String, DownloadInfo>, file: Found) -> DownloadInfo {
+  let filename = file.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+  let file_url = url::Url::from_file_path(&file.path)
+    .map(String::from)
+    .unwrap_or_else(|()| format!("file://{}", file.path.display()));
+  let mut download = DownloadInfo {
+    id: uuid::Uuid::new_v4().to_string(),
+    url: file_url,
+    filename,
+    status: "completed".to_string(),
+    progress: 100.0,
+    path: file.path,
+    subdir: file.subdir,
+    bytes_downloaded: file.size,
+    total_bytes: Some(file.size),
+    created_at: file.modified,
+    ..Default::default()
+  };
+  if let Some(sidecar) = file.sidecar {
+    // The sidecar's id is kept unless another download already has it
+    if !sidecar.id.is_empty() && !downloads.contains_key(&sidecar.id) {
+      download.id = sidecar.id;
+    }
+    if !sidecar.url.is_empty() {
+      download.url = sidecar.url;
+    }
+    download.source_url = sidecar.source_url;
+    download.created_at = sidecar.created_at;
+    download.etag = sidecar.etag;
+    download.last_modified = sidecar.last_modified;
+    download.content_encoding = sidecar.content_encoding;
+    download.tags = sidecar.tags;
+    download.options.headers = sidecar.headers;
+    if sidecar.size == file.size {
+      download.sha256 = sidecar.sha256;
+      download.size_verified = true;
+    } else {
+      download.status = "corrupt".to_string();
+      download.error = Some(format!("File is {} bytes, its sidecar says {}", file.size, sidecar.size));
+    }
+  }
+  download.queue_position = download.created_at;
+  download.normalized_url = crate::normalize_url(&download.url);
+  download
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn merging_adds_untracked_files_once() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-reindex-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("manual.bin"), b"abc").unwrap();
+    std::fs::write(dir.join("tracked.bin"), b"abcd").unwrap();
+    std::fs::write(dir.join(".hidden"), b"x").unwrap();
+    let known = DownloadInfo {
+      id: "old".to_string(),
+      url: "https://example.com/clip.mp4".to_string(),
+      filename: "clip.mp4".to_string(),
+      bytes_downloaded: 5,
+      sha256: Some("cd".repeat(32)),
+      ..Default::default()
+    };
+    std::fs::write(dir.join("clip.mp4"), b"12345").unwrap();
+    sidecar::write(&dir.join("clip.mp4"), &Sidecar::new(&known, 1)).unwrap();
+
+    let mut downloads = HashMap::new();
+    let tracked = DownloadInfo {
+      id: "tracked".to_string(),
+      status: "missing".to_string(),
+      path: dir.join("tracked.bin"),
+      bytes_downloaded: 4,
+      ..Default::default()
+    };
+    downloads.insert(tracked.id.clone(), tracked);
+
+    let (summary, added) = merge(&mut downloads, scan(&dir).unwrap());
+    assert_eq!(summary, ReindexSummary { added: 2, updated: 1 });
+    assert_eq!(added.len(), 2);
+    assert_eq!(downloads["tracked"].status, "completed");
+    assert_eq!(downloads["old"].url, known.url);
+    assert_eq!(downloads["old"].sha256, known.sha256);
+
+    let (again, _) = merge(&mut downloads, scan(&dir).unwrap());
+    assert_eq!(again, ReindexSummary::default());
+    assert_eq!(downloads.len(), 3);
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn scanning_finds_files_in_subdirectories() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-reindex-nested-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("Music").join("Live")).unwrap();
+    std::fs::create_dir_all(dir.join(".cache")).unwrap();
+    std::fs::write(dir.join("Music").join("Live").join("track.mp3"), b"abc").unwrap();
+    std::fs::write(dir.join(".cache").join("skipped.bin"), b"x").unwrap();
+
+    let mut downloads = HashMap::new();
+    let (summary, added) = merge(&mut downloads, scan(&dir).unwrap());
+    assert_eq!(summary.added, 1);
+    let download = &downloads[&added[0]];
+    assert_eq!(download.subdir.as_deref(), Some("Music/Live"));
+    assert_eq!(download.path, dir.join("Music").join("Live").join("track.mp3"));
+    assert_eq!(download.filename, "track.mp3");
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
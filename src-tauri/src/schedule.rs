@@ -0,0 +1,69 @@
+// Deferred starts. A download queued with options.start_after in the future
+// waits as "scheduled" until then; the watcher below moves it to "queued" and
+// starts it, and the concurrency limit decides when it actually transfers.
+use std::time::Duration;
+
+use tauri::Manager;
+
+use crate::{AppHandle, AppState};
+
+// Longest sleep between checks, so a changed system clock or a wake from
+// suspend is noticed without waiting for a far-off start time
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+// Whether a download queued now with this start time has to wait for it
+pub(crate) fn is_future(start_after: Option<i64>) -> bool {
+  start_after.is_some_and(|at| at > chrono::Utc::now().timestamp_millis())
+}
+
+// Promotes due downloads, then sleeps until the next start time, MAX_SLEEP or
+// a change to the schedule, whichever comes first
+pub(crate) async fn watch(app: AppHandle) {
+  loop {
+    let next = promote_due(&app).await;
+    let now = chrono::Utc::now().timestamp_millis();
+    let wait = next
+      .map(|at| Duration::from_millis(at.saturating_sub(now).max(0) as u64))
+      .unwrap_or(MAX_SLEEP)
+      .min(MAX_SLEEP);
+    let state = app.state::<AppState>();
+    tokio::select! {
+      _ = tokio::time::sleep(wait) => {}
+      _ = state.schedule_changed.notified() => {}
+    }
+  }
+}
+
+// Queues and starts every scheduled download whose time has come, earliest
+// first. Returns the start time of the next one still waiting.
+async fn promote_due(app: &AppHandle) -> Option<i64> {
+  let state = app.state::<AppState>();
+  let now = chrono::Utc::now().timestamp_millis();
+  let (mut due, next) = {
+    let mut downloads = state.downloads.lock().await;
+    let mut due = Vec::new();
+    let mut next: Option<i64> = None;
+    for download in downloads.values_mut().filter(|download| download.status == "scheduled") {
+      let at = download.options.start_after.unwrap_or(now);
+      if at <= now {
+        download.status = "queued".to_string();
+        due.push((at, download.created_at, download.id.clone()));
+      } else {
+        next = Some(next.map_or(at, |next| next.min(at)));
+      }
+    }
+    (due, next)
+  };
+  if due.is_empty() {
+    return next;
+  }
+  due.sort();
+  crate::persistence::save(&state).await;
+  for (_, _, id) in &due {
+    if let Err(e) = crate::downloader::spawn(app, id.clone()) {
+      log::warn!("Failed to start scheduled download {}: {}", id, e);
+    }
+  }
+  log::info!("Started {} scheduled downloads", due.len());
+  next
+}
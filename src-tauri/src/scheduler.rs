@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+use crate::lock::LockExt;
+
+pub(crate) const DEFAULT_MAX_CONCURRENT: usize = 3;
+pub(crate) const MAX_CONCURRENT_LIMIT: usize = 16;
+
+// Caps how many downloads transfer at once. Download tasks wait for a permit
+// while "queued" and hold it until they finish, pause or fail. A free slot goes
+// to the waiting download with the lowest queue position rather than to the
+// one that asked first, so reorder_download decides what starts next.
+pub(crate) struct ConcurrencyLimit {
+  shared: Arc<Shared>,
+}
+
+struct Shared {
+  slots: Mutex<Slots>,
+  // Wakes waiters whenever a slot frees up, the limit changes or the order
+  // of the queue does
+  changed: Notify,
+}
+
+struct Slots {
+  limit: usize,
+  running: usize,
+  // Queue position of every download waiting for a slot
+  waiting: HashMap<String, i64>,
+}
+
+impl Slots {
+  // Whether `id` is first in line among the waiting downloads
+  fn is_next(&self, id: &str) -> bool {
+    let Some(position) = self.waiting.get(id) else {
+      return false;
+    };
+    self
+      .waiting
+      .iter()
+      .all(|(other, other_position)| (position, id) <= (other_position, other.as_str()))
+  }
+}
+
+// A taken slot, returned when dropped
+pub(crate) struct Permit {
+  shared: Arc<Shared>,
+}
+
+impl Drop for Permit {
+  fn drop(&mut self) {
+    self.shared.slots.lock_or_recover().running -= 1;
+    self.shared.changed.notify_waiters();
+  }
+}
+
+// Takes the download off the waiting list when acquire is abandoned, e.g. by
+// a pause while it waits
+struct Waiting<'a> {
+  shared: &'a Shared,
+  id: &'a str,
+}
+
+impl Drop for Waiting<'_> {
+  fn drop(&mut self) {
+    self.shared.slots.lock_or_recover().waiting.remove(self.id);
+    // Someone behind this download may be first in line now
+    self.shared.changed.notify_waiters();
+  }
+}
+
+impl ConcurrencyLimit {
+  pub(crate) fn new(limit: usize) -> Self {
+    Self {
+      shared: Arc::new(Shared {
+        slots: Mutex::new(Slots { limit, running: 0, waiting: HashMap::new() }),
+        changed: Notify::new(),
+      }),
+    }
+  }
+
+  // Waits until a slot is free and no download with a lower position is
+  // waiting for it
+  pub(crate) async fn acquire(&self, id: &str, position: i64) -> Permit {
+    let shared = &*self.shared;
+    shared.slots.lock_or_recover().waiting.insert(id.to_string(), position);
+    let waiting = Waiting { shared, id };
+    loop {
+      // Registered before checking so a change in between isn't missed
+      let changed = shared.changed.notified();
+      tokio::pin!(changed);
+      changed.as_mut().enable();
+      {
+        let mut slots = shared.slots.lock_or_recover();
+        if slots.running < slots.limit && slots.is_next(id) {
+          slots.running += 1;
+          drop(slots);
+          // Removes this download from the list and lets the next in line
+          // check whether a slot is left for it too
+          drop(waiting);
+          return Permit { shared: self.shared.clone() };
+        }
+      }
+      changed.await;
+    }
+  }
+
+  // Moves waiting downloads to new queue positions; downloads that aren't
+  // waiting pick theirs up when they ask for a slot
+  pub(crate) fn set_positions(&self, positions: &[(String, i64)]) -> Result<(), String> {
+    let mut slots = self.shared.slots.lock_or_recover();
+    for (id, position) in positions {
+      if let Some(waiting) = slots.waiting.get_mut(id) {
+        *waiting = *position;
+      }
+    }
+    drop(slots);
+    self.shared.changed.notify_waiters();
+    Ok(())
+  }
+
+  pub(crate) fn limit(&self) -> usize {
+    self.shared.slots.lock_or_recover().limit
+  }
+
+  // Raising the limit admits waiting downloads immediately. Lowering it only
+  // holds back new starts until enough running downloads finish, so nothing
+  // already transferring is interrupted.
+  pub(crate) fn set_limit(&self, new_limit: usize) -> Result<(), String> {
+    let mut slots = self.shared.slots.lock_or_recover();
+    slots.limit = new_limit;
+    drop(slots);
+    self.shared.changed.notify_waiters();
+    Ok(())
+  }
+}
+
+// Caps how many downloads transfer from one host at once, on top of the
+// global limit, so a batch from a single server doesn't open enough
+// connections to get blocked. Tasks take a host permit before a global slot,
+// which leaves the slot free for downloads from other hosts meanwhile. Counts
+// only exist for hosts with a running download, so the map never grows past
+// the downloads in flight.
+pub(crate) struct HostLimit {
+  shared: Arc<HostShared>,
+}
+
+struct HostShared {
+  hosts: Mutex<HostSlots>,
+  changed: Notify,
+}
+
+struct HostSlots {
+  // None when hosts are only bound by the global limit
+  limit: Option<usize>,
+  running: HashMap<String, usize>,
+}
+
+// A taken host slot, returned when dropped
+pub(crate) struct HostPermit {
+  shared: Arc<HostShared>,
+  host: String,
+}
+
+impl Drop for HostPermit {
+  fn drop(&mut self) {
+    let mut hosts = self.shared.hosts.lock_or_recover();
+    if let Some(running) = hosts.running.get_mut(&self.host) {
+      *running -= 1;
+      if *running == 0 {
+        hosts.running.remove(&self.host);
+      }
+    }
+    drop(hosts);
+    self.shared.changed.notify_waiters();
+  }
+}
+
+impl HostLimit {
+  pub(crate) fn new(limit: Option<usize>) -> Self {
+    Self {
+      shared: Arc::new(HostShared {
+        hosts: Mutex::new(HostSlots { limit, running: HashMap::new() }),
+        changed: Notify::new(),
+      }),
+    }
+  }
+
+  // Waits until fewer than the limit of downloads from `host` are running
+  pub(crate) async fn acquire(&self, host: &str) -> HostPermit {
+    let shared = &*self.shared;
+    loop {
+      let changed = shared.changed.notified();
+      tokio::pin!(changed);
+      changed.as_mut().enable();
+      {
+        let mut hosts = shared.hosts.lock_or_recover();
+        let limit = hosts.limit;
+        let running = hosts.running.entry(host.to_string()).or_default();
+        if limit.map_or(true, |limit| *running < limit) {
+          *running += 1;
+          return HostPermit { shared: self.shared.clone(), host: host.to_string() };
+        }
+      }
+      changed.await;
+    }
+  }
+
+  // Like ConcurrencyLimit::set_limit, lowering it never interrupts a transfer
+  pub(crate) fn set_limit(&self, limit: Option<usize>) {
+    self.shared.hosts.lock_or_recover().limit = limit;
+    self.shared.changed.notify_waiters();
+  }
+
+  #[cfg(test)]
+  fn tracked_hosts(&self) -> usize {
+    self.shared.hosts.lock_or_recover().running.len()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  #[test]
+  fn free_slots_go_to_the_lowest_position() {
+    tauri::async_runtime::block_on(async {
+      let limit = Arc::new(ConcurrencyLimit::new(1));
+      let first = limit.acquire("running", 0).await;
+
+      let order = Arc::new(Mutex::new(Vec::new()));
+      let mut tasks = Vec::new();
+      for (id, position) in [("late", 20), ("early", 10), ("last", 30)] {
+        let (limit, order) = (limit.clone(), order.clone());
+        tasks.push(tauri::async_runtime::spawn(async move {
+          let _permit = limit.acquire(id, position).await;
+          order.lock().unwrap().push(id);
+          tokio::time::sleep(Duration::from_millis(5)).await;
+        }));
+      }
+      tokio::time::sleep(Duration::from_millis(20)).await;
+      limit.set_positions(&[("last".to_string(), 5)]).unwrap();
+      drop(first);
+      for task in tasks {
+        task.await.unwrap();
+      }
+      assert_eq!(*order.lock().unwrap(), vec!["last", "early", "late"]);
+    });
+  }
+
+  #[test]
+  fn hosts_are_limited_separately_and_forgotten_when_idle() {
+    tauri::async_runtime::block_on(async {
+      let limit = HostLimit::new(Some(1));
+      let first = limit.acquire("a.example.com").await;
+      let other = limit.acquire("b.example.com").await;
+      let blocked = tokio::time::timeout(Duration::from_millis(20), limit.acquire("a.example.com")).await;
+      assert!(blocked.is_err());
+
+      drop(first);
+      let second = tokio::time::timeout(Duration::from_millis(20), limit.acquire("a.example.com")).await;
+      assert!(second.is_ok());
+      drop((second, other));
+      assert_eq!(limit.tracked_hosts(), 0);
+    });
+  }
+}
@@ -0,0 +1,288 @@
+// Byte range planning for downloads split across several connections, and
+// reading the ranged responses they get back
+
+// Segments smaller than this aren't worth an extra connection
+pub(crate) const MIN_SEGMENT_SIZE: u64 = 1024 * 1024;
+// Upper bound for DownloadOptions::connections
+pub(crate) const MAX_CONNECTIONS: u32 = 16;
+
+// Splits a file of `total` bytes into at most `connections` inclusive ranges
+// of near equal size, each at least MIN_SEGMENT_SIZE. Fewer than two ranges
+// means the file should be fetched over a single connection.
+pub(crate) fn plan(total: u64, connections: u32) -> Vec<(u64, u64)> {
+  if total == 0 {
+    return Vec::new();
+  }
+  let count = (total / MIN_SEGMENT_SIZE).clamp(1, u64::from(connections.max(1)));
+  let size = total.div_ceil(count);
+  (0..count)
+    .map(|i| (i * size, ((i + 1) * size).min(total) - 1))
+    .collect()
+}
+
+// Parses a Content-Range value like "bytes 100-199/1000" into the first and
+// last byte and the complete length, which is None for "*"
+pub(crate) fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+  let range = value.trim().strip_prefix("bytes")?.trim_start();
+  let (span, total) = range.split_once('/')?;
+  let (start, end) = span.split_once('-')?;
+  let start = start.trim().parse().ok()?;
+  let end = end.trim().parse().ok()?;
+  let total = match total.trim() {
+    "*" => None,
+    total => Some(total.parse().ok()?),
+  };
+  (start <= end).then_some((start, end, total))
+}
+
+// The complete length from the "bytes */1000" a 416 response carries
+pub(crate) fn unsatisfied_length(value: &str) -> Option<u64> {
+  value.trim().strip_prefix("bytes")?.trim_start().strip_prefix("*/")?.trim().parse().ok()
+}
+
+// Longest preamble or part header block accepted in a multipart/byteranges
+// body before it is taken for something else
+const MAX_MULTIPART_HEADER_BYTES: usize = 16 * 1024;
+
+// Reads the boundary from a "multipart/byteranges; boundary=..." Content-Type.
+// None for any other type; an error when the boundary is missing, since the
+// body then can't be split.
+pub(crate) fn byteranges_boundary(content_type: &str) -> Result<Option<String>, String> {
+  let mut params = content_type.split(';');
+  if !params.next().is_some_and(|media| media.trim().eq_ignore_ascii_case("multipart/byteranges")) {
+    return Ok(None);
+  }
+  params
+    .filter_map(|param| param.split_once('='))
+    .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+    .map(|(_, value)| value.trim().trim_matches('"').to_string())
+    .filter(|boundary| !boundary.is_empty())
+    .map(Some)
+    .ok_or_else(|| "multipart/byteranges response has no boundary".to_string())
+}
+
+// Splits a multipart/byteranges body, as fed in chunks of any size, into the
+// bytes of each part and the file offset they belong at, taken from the
+// part's Content-Range. Part bodies are as long as their range says rather
+// than found by scanning for the boundary, so a body that runs over or stops
+// short of it is an error instead of being written.
+pub(crate) struct ByteRanges {
+  // "\r\n--boundary"; the CRLF belongs to the delimiter, and a leading one is
+  // assumed so the first delimiter matches at the very start too
+  delimiter: Vec<u8>,
+  // Complete length every part's Content-Range must state
+  total: u64,
+  buffer: Vec<u8>,
+  state: PartState,
+}
+
+enum PartState {
+  // Before the first delimiter
+  Preamble,
+  // Expecting the delimiter that ends a part body
+  Delimiter,
+  // After a delimiter: "--" ends the body, CRLF starts a part
+  AfterDelimiter,
+  Headers,
+  Body { offset: u64, remaining: u64 },
+  // After the final delimiter; anything more is ignored
+  Epilogue,
+}
+
+impl ByteRanges {
+  pub(crate) fn new(boundary: &str, total: u64) -> Self {
+    Self {
+      delimiter: format!("\r\n--{}", boundary).into_bytes(),
+      total,
+      buffer: b"\r\n".to_vec(),
+      state: PartState::Preamble,
+    }
+  }
+
+  // Consumes the next chunk of the body, returning the part data completed
+  // by it as (offset, bytes) in body order
+  pub(crate) fn feed(&mut self, input: &[u8]) -> Result<Vec<(u64, Vec<u8>)>, String> {
+    self.buffer.extend_from_slice(input);
+    let mut parts = Vec::new();
+    loop {
+      match self.state {
+        PartState::Preamble => match find(&self.buffer, &self.delimiter) {
+          Some(at) => {
+            self.buffer.drain(..at + self.delimiter.len());
+            self.state = PartState::AfterDelimiter;
+          }
+          None if self.buffer.len() > MAX_MULTIPART_HEADER_BYTES => {
+            return Err("multipart/byteranges body has no boundary delimiter".to_string());
+          }
+          None => return Ok(parts),
+        },
+        PartState::Delimiter => {
+          if self.buffer.len() < self.delimiter.len() {
+            return Ok(parts);
+          }
+          if !self.buffer.starts_with(&self.delimiter) {
+            return Err("multipart/byteranges part is longer than its Content-Range".to_string());
+          }
+          self.buffer.drain(..self.delimiter.len());
+          self.state = PartState::AfterDelimiter;
+        }
+        PartState::AfterDelimiter => {
+          // Transport padding may follow the boundary
+          let padding = self.buffer.iter().take_while(|b| matches!(b, b' ' | b'\t')).count();
+          self.buffer.drain(..padding);
+          if self.buffer.len() < 2 {
+            return Ok(parts);
+          }
+          self.state = match &self.buffer[..2] {
+            b"--" => PartState::Epilogue,
+            b"\r\n" => PartState::Headers,
+            _ => return Err("multipart/byteranges boundary is followed by garbage".to_string()),
+          };
+          self.buffer.drain(..2);
+        }
+        PartState::Headers => {
+          // With the CRLF after the delimiter consumed, an empty header block
+          // is a lone CRLF
+          let block = if self.buffer.starts_with(b"\r\n") {
+            Some(0)
+          } else {
+            find(&self.buffer, b"\r\n\r\n").map(|at| at + 2)
+          };
+          let Some(end) = block else {
+            if self.buffer.len() > MAX_MULTIPART_HEADER_BYTES {
+              return Err("multipart/byteranges part headers are too long".to_string());
+            }
+            return Ok(parts);
+          };
+          let headers = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+          self.buffer.drain(..end + 2);
+          let range = headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-range"))
+            .and_then(|(_, value)| parse_content_range(value));
+          let (first, last) = match range {
+            Some((first, last, total)) if last < self.total && total.map_or(true, |total| total == self.total) => {
+              (first, last)
+            }
+            Some(_) => return Err("multipart/byteranges part describes a different file".to_string()),
+            None => return Err("multipart/byteranges part has no valid Content-Range".to_string()),
+          };
+          self.state = PartState::Body { offset: first, remaining: last - first + 1 };
+        }
+        PartState::Body { offset, remaining } => {
+          if self.buffer.is_empty() {
+            return Ok(parts);
+          }
+          let take = remaining.min(self.buffer.len() as u64);
+          parts.push((offset, self.buffer.drain(..take as usize).collect()));
+          self.state = if take == remaining {
+            PartState::Delimiter
+          } else {
+            PartState::Body { offset: offset + take, remaining: remaining - take }
+          };
+        }
+        PartState::Epilogue => {
+          self.buffer.clear();
+          return Ok(parts);
+        }
+      }
+    }
+  }
+
+  // Called at the end of the body, which must have reached the final delimiter
+  pub(crate) fn finish(&self) -> Result<(), String> {
+    match self.state {
+      PartState::Epilogue => Ok(()),
+      _ => Err("multipart/byteranges body ended before its final boundary".to_string()),
+    }
+  }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+  haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn plans_contiguous_ranges_covering_the_file() {
+    let total = 10 * MIN_SEGMENT_SIZE + 3;
+    let ranges = plan(total, 4);
+    assert_eq!(ranges.len(), 4);
+    assert_eq!(ranges[0].0, 0);
+    assert_eq!(ranges[3].1, total - 1);
+    for pair in ranges.windows(2) {
+      assert_eq!(pair[0].1 + 1, pair[1].0);
+    }
+  }
+
+  #[test]
+  fn small_files_use_fewer_connections() {
+    assert_eq!(plan(MIN_SEGMENT_SIZE / 2, 8), vec![(0, MIN_SEGMENT_SIZE / 2 - 1)]);
+    assert_eq!(plan(3 * MIN_SEGMENT_SIZE, 8).len(), 3);
+    assert_eq!(plan(100 * MIN_SEGMENT_SIZE, 1).len(), 1);
+    assert!(plan(0, 4).is_empty());
+  }
+
+  #[test]
+  fn parses_content_range() {
+    assert_eq!(parse_content_range("bytes 0-99/1000"), Some((0, 99, Some(1000))));
+    assert_eq!(parse_content_range("bytes 500-999/*"), Some((500, 999, None)));
+    assert_eq!(parse_content_range("bytes */1000"), None);
+    assert_eq!(parse_content_range("bytes 9-1/10"), None);
+    assert_eq!(parse_content_range("items 0-1/2"), None);
+    assert_eq!(unsatisfied_length("bytes */1000"), Some(1000));
+    assert_eq!(unsatisfied_length("bytes 0-99/1000"), None);
+  }
+
+  const MULTIPART: &[u8] = b"preamble\r\n--XY\r\nContent-Type: video/mp4\r\nContent-Range: bytes 2-5/10\r\n\r\n\
+2345\r\n--XY \r\ncontent-range: bytes 6-7/10\r\n\r\n67\r\n--XY--\r\nepilogue";
+
+  // Feeds the body in pieces of `size` bytes and joins the parts per offset
+  fn demux(body: &[u8], size: usize) -> Result<Vec<(u64, Vec<u8>)>, String> {
+    let mut ranges = ByteRanges::new("XY", 10);
+    let mut joined: Vec<(u64, Vec<u8>)> = Vec::new();
+    for chunk in body.chunks(size) {
+      for (offset, bytes) in ranges.feed(chunk)? {
+        match joined.last_mut() {
+          Some((start, data)) if *start + data.len() as u64 == offset => data.extend(bytes),
+          _ => joined.push((offset, bytes)),
+        }
+      }
+    }
+    ranges.finish()?;
+    Ok(joined)
+  }
+
+  #[test]
+  fn reads_byteranges_boundary() {
+    assert_eq!(byteranges_boundary("multipart/byteranges; boundary=XY"), Ok(Some("XY".to_string())));
+    assert_eq!(byteranges_boundary("Multipart/ByteRanges;boundary=\"a b\""), Ok(Some("a b".to_string())));
+    assert_eq!(byteranges_boundary("video/mp4"), Ok(None));
+    assert!(byteranges_boundary("multipart/byteranges").is_err());
+  }
+
+  #[test]
+  fn splits_multipart_byteranges_in_any_chunking() {
+    let expected = vec![(2, b"234567".to_vec())];
+    for size in [1, 2, 3, 7, 16, MULTIPART.len()] {
+      assert_eq!(demux(MULTIPART, size), Ok(expected.clone()), "chunk size {}", size);
+    }
+    let gap = b"--XY\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--XY\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--XY--";
+    assert_eq!(demux(gap, 4), Ok(vec![(0, b"01".to_vec()), (8, b"89".to_vec())]));
+  }
+
+  #[test]
+  fn rejects_malformed_multipart_byteranges() {
+    let long = b"--XY\r\nContent-Range: bytes 2-3/10\r\n\r\n2345\r\n--XY--";
+    let no_range = b"--XY\r\nContent-Type: video/mp4\r\n\r\n23\r\n--XY--";
+    let other_file = b"--XY\r\nContent-Range: bytes 2-3/99\r\n\r\n23\r\n--XY--";
+    let truncated = b"--XY\r\nContent-Range: bytes 2-5/10\r\n\r\n23";
+    for body in [&long[..], no_range, other_file, truncated, b"no delimiter at all"] {
+      assert!(demux(body, 3).is_err(), "{}", String::from_utf8_lossy(body));
+    }
+  }
+}
@@ -0,0 +1,168 @@
+// Optional "<file>.meta.json" files written next to completed downloads when
+// set_write_sidecar is on. They describe where a file came from so download
+// history can be rebuilt from a folder without the app's state file.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, DownloadInfo, SECRET_HEADERS};
+
+pub(crate) const SUFFIX: &str = ".meta.json";
+// Bumped when the layout changes incompatibly
+pub(crate) const SIDECAR_VERSION: u64 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct Sidecar {
+  pub version: u64,
+  pub id: String,
+  pub url: String,
+  // Mirror that served the file, when it wasn't the primary URL
+  pub source_url: Option<String>,
+  pub filename: String,
+  // Milliseconds since the Unix epoch
+  pub created_at: i64,
+  pub completed_at: i64,
+  pub size: u64,
+  pub sha256: Option<String>,
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+  pub content_encoding: Option<String>,
+  // Custom request headers the download was made with, credentials removed
+  pub headers: HashMap<String, String>,
+  pub tags: Vec<String>,
+}
+
+impl Sidecar {
+  pub(crate) fn new(download: &DownloadInfo, completed_at: i64) -> Self {
+    let headers = download
+      .options
+      .headers
+      .iter()
+      .filter(|(name, _)| !SECRET_HEADERS.contains(&name.as_str()))
+      .map(|(name, value)| (name.clone(), value.clone()))
+      .collect();
+    Self {
+      version: SIDECAR_VERSION,
+      id: download.id.clone(),
+      url: download.url.clone(),
+      source_url: download.source_url.clone().filter(|source| *source != download.url),
+      filename: download.filename.clone(),
+      created_at: download.created_at,
+      completed_at,
+      size: download.bytes_downloaded,
+      sha256: download.sha256.clone(),
+      etag: download.etag.clone(),
+      last_modified: download.last_modified.clone(),
+      content_encoding: download.content_encoding.clone(),
+      headers,
+      tags: download.tags.clone(),
+    }
+  }
+}
+
+// "clip.mp4" -> "clip.mp4.meta.json" in the same directory
+pub(crate) fn path_for(file: &Path) -> PathBuf {
+  let mut name = file.file_name().unwrap_or_default().to_os_string();
+  name.push(SUFFIX);
+  file.with_file_name(name)
+}
+
+// Writes the sidecar of `file` through a temporary file, so a crash never
+// leaves a truncated one behind
+pub(crate) fn write(file: &Path, sidecar: &Sidecar) -> Result<(), String> {
+  let target = path_for(file);
+  let json = serde_json::to_string_pretty(sidecar).map_err(|e| format!("Failed to serialize sidecar: {}", e))?;
+  let temp = target.with_extension("json.tmp");
+  std::fs::write(&temp, json)
+    .and_then(|()| std::fs::rename(&temp, &target))
+    .map_err(|e| {
+      let _ = std::fs::remove_file(&temp);
+      format!("Failed to write {}: {}", target.display(), e)
+    })
+}
+
+// Reads a sidecar, refusing versions newer than this build understands
+pub(crate) fn read(path: &Path) -> Result<Sidecar, String> {
+  let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+  let sidecar: Sidecar =
+    serde_json::from_str(&json).map_err(|e| format!("Invalid sidecar {}: {}", path.display(), e))?;
+  if sidecar.version == 0 || sidecar.version > SIDECAR_VERSION {
+    return Err(format!("Unsupported sidecar version {} in {}", sidecar.version, path.display()));
+  }
+  Ok(sidecar)
+}
+
+// Whether the file is a sidecar or one being written
+pub(crate) fn is_sidecar(path: &Path) -> bool {
+  let name = path.file_name().unwrap_or_default().to_string_lossy();
+  name.ends_with(SUFFIX) || name.ends_with(".meta.json.tmp")
+}
+
+// Whether completed downloads get a sidecar
+pub(crate) fn enabled(state: &AppState) -> bool {
+  state.write_sidecar.load(Ordering::Relaxed)
+}
+
+// Writes the sidecar of a completed download. Failing to write one is logged
+// and otherwise ignored, since the download itself succeeded.
+pub(crate) async fn write_for(state: &AppState, download_id: &str) {
+  let Some(download) = state.downloads.lock().await.get(download_id).cloned() else {
+    return;
+  };
+  let sidecar = Sidecar::new(&download, chrono::Utc::now().timestamp_millis());
+  let written = tauri::async_runtime::spawn_blocking(move || write(&download.path, &sidecar))
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|written| written);
+  if let Err(e) = written {
+    log::warn!("No sidecar for download {}: {}", download_id, e);
+  }
+}
+
+// Deletes the sidecar of `file` if there is one
+pub(crate) fn remove(file: &Path) {
+  match std::fs::remove_file(path_for(file)) {
+    Ok(()) => {}
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+    Err(e) => log::warn!("Failed to delete the sidecar of {}: {}", file.display(), e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sidecars_round_trip_without_credentials() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-sidecar-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut download = DownloadInfo {
+      id: "a".to_string(),
+      url: "https://example.com/clip.mp4".to_string(),
+      filename: "clip.mp4".to_string(),
+      bytes_downloaded: 42,
+      sha256: Some("ab".repeat(32)),
+      ..Default::default()
+    };
+    download.options.headers.insert("referer".to_string(), "https://example.com/".to_string());
+    download.options.headers.insert("authorization".to_string(), "Bearer secret".to_string());
+    let file = dir.join("clip.mp4");
+
+    write(&file, &Sidecar::new(&download, 1_000)).unwrap();
+    assert_eq!(path_for(&file), dir.join("clip.mp4.meta.json"));
+    assert!(is_sidecar(&path_for(&file)));
+    let sidecar = read(&path_for(&file)).unwrap();
+    assert_eq!(sidecar.url, download.url);
+    assert_eq!(sidecar.size, 42);
+    assert_eq!(sidecar.completed_at, 1_000);
+    assert!(sidecar.headers.contains_key("referer"));
+    assert!(!sidecar.headers.contains_key("authorization"));
+
+    remove(&file);
+    assert!(!path_for(&file).exists());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
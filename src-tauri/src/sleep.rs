@@ -0,0 +1,209 @@
+// Pauses downloads before the system sleeps and resumes them after it wakes,
+// unless turned off with set_pause_on_sleep. A connection that dies during
+// suspend can otherwise end a transfer early without an error, leaving a
+// truncated file; pausing first flushes the partial file so the download
+// resumes from it. Notifications come from logind on Linux and the power
+// manager on Windows; elsewhere this is a no-op. On Linux a delay lock makes
+// logind wait for the pause before it suspends.
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use tauri::Manager;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{AppHandle, AppState};
+
+// hold_reason of downloads paused for sleep
+pub(crate) const HOLD_REASON: &str = "sleep";
+// Time the network gets to come back before downloads resume
+const WAKE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PowerEvent {
+  Sleep,
+  Wake,
+}
+
+// Handles sleep and wake notifications for as long as the app runs
+pub(crate) async fn watch(app: AppHandle) {
+  let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+  if let Err(e) = listen(sender) {
+    log::info!("Sleep notifications are unavailable ({}); downloads aren't paused for sleep", e);
+    return;
+  }
+  let mut delay = SleepDelay::take();
+  while let Some(event) = events.recv().await {
+    match event {
+      PowerEvent::Sleep => {
+        if app.state::<AppState>().pause_on_sleep.load(Ordering::Relaxed) {
+          hold_active(&app).await;
+        }
+        delay.release();
+      }
+      // Downloads paused for an earlier sleep resume even if the setting was
+      // turned off since
+      PowerEvent::Wake => {
+        delay = SleepDelay::take();
+        tokio::time::sleep(WAKE_DELAY).await;
+        release(&app).await;
+      }
+    }
+  }
+}
+
+// Pauses everything running or waiting and waits for the tasks to close
+// their files. The system sleeps once the delay lock is released, or after
+// logind's InhibitDelayMaxSec (5 seconds by default); Windows gives about two.
+async fn hold_active(app: &AppHandle) {
+  let held = crate::hold::hold_active(app, HOLD_REASON, "paused").await;
+  if held > 0 {
+    log::info!("Paused {} downloads for sleep", held);
+  }
+}
+
+// Resumes the downloads paused for sleep
+async fn release(app: &AppHandle) {
+  let released = crate::hold::release(app, HOLD_REASON, &["paused"]).await;
+  if released > 0 {
+    log::info!("Resuming {} downloads paused for sleep", released);
+  }
+}
+
+// A logind "delay" inhibitor lock on sleep, held through systemd-inhibit for
+// as long as its stdin stays open. Dropping the pipe ends it and lets the
+// system sleep; a crashed app lets go the same way.
+#[cfg(target_os = "linux")]
+struct SleepDelay(Option<std::process::Child>);
+
+#[cfg(target_os = "linux")]
+impl SleepDelay {
+  fn take() -> Self {
+    use std::process::{Command, Stdio};
+
+    let child = Command::new("systemd-inhibit")
+      .args([
+        "--what=sleep",
+        "--mode=delay",
+        "--who=StreamHaven",
+        "--why=Pausing downloads before sleep",
+        "cat",
+      ])
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()
+      .map_err(|e| log::info!("Can't delay sleep ({}); downloads may be cut off by a suspend", e))
+      .ok();
+    Self(child)
+  }
+
+  fn release(&mut self) {
+    if let Some(mut child) = self.0.take() {
+      drop(child.stdin.take());
+      let _ = child.wait();
+    }
+  }
+}
+
+// Windows has no such lock; it gives the suspend notification about two seconds
+#[cfg(not(target_os = "linux"))]
+struct SleepDelay;
+
+#[cfg(not(target_os = "linux"))]
+impl SleepDelay {
+  fn take() -> Self {
+    Self
+  }
+
+  fn release(&mut self) {}
+}
+
+// logind's PrepareForSleep signal, read through dbus-monitor: true before
+// suspending, false after resuming
+#[cfg(target_os = "linux")]
+fn listen(sender: UnboundedSender<PowerEvent>) -> Result<(), String> {
+  use std::io::{BufRead, BufReader};
+  use std::process::{Command, Stdio};
+
+  let mut child = Command::new("dbus-monitor")
+    .args([
+      "--system",
+      "type='signal',interface='org.freedesktop.login1.Manager',member='PrepareForSleep'",
+    ])
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .map_err(|e| format!("dbus-monitor: {}", e))?;
+  let stdout = child.stdout.take().ok_or("dbus-monitor has no output")?;
+  std::thread::spawn(move || {
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+      let event = match line.trim() {
+        "boolean true" => PowerEvent::Sleep,
+        "boolean false" => PowerEvent::Wake,
+        _ => continue,
+      };
+      if sender.send(event).is_err() {
+        break;
+      }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+  });
+  Ok(())
+}
+
+#[cfg(windows)]
+static SENDER: std::sync::OnceLock<UnboundedSender<PowerEvent>> = std::sync::OnceLock::new();
+
+// Suspend and resume notifications from the power manager, delivered to a
+// callback on a system thread
+#[cfg(windows)]
+fn listen(sender: UnboundedSender<PowerEvent>) -> Result<(), String> {
+  use windows::Win32::Foundation::{ERROR_SUCCESS, HANDLE};
+  use windows::Win32::System::Power::{PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS};
+  use windows::Win32::UI::WindowsAndMessaging::{
+    DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMRESUMESUSPEND, PBT_APMSUSPEND,
+  };
+
+  unsafe extern "system" fn callback(
+    _context: *const core::ffi::c_void,
+    event: u32,
+    _setting: *const core::ffi::c_void,
+  ) -> u32 {
+    let event = match event {
+      PBT_APMSUSPEND => Some(PowerEvent::Sleep),
+      PBT_APMRESUMEAUTOMATIC | PBT_APMRESUMESUSPEND => Some(PowerEvent::Wake),
+      _ => None,
+    };
+    if let (Some(event), Some(sender)) = (event, SENDER.get()) {
+      let _ = sender.send(event);
+    }
+    ERROR_SUCCESS.0
+  }
+
+  SENDER.set(sender).map_err(|_| "already listening".to_string())?;
+  // Registered for the lifetime of the process, so the parameters are too
+  let parameters: &'static DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+    Callback: Some(callback),
+    Context: std::ptr::null_mut(),
+  }));
+  let mut registration = std::ptr::null_mut();
+  let result = unsafe {
+    PowerRegisterSuspendResumeNotification(
+      DEVICE_NOTIFY_CALLBACK,
+      HANDLE(parameters as *const DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS as *mut core::ffi::c_void),
+      &mut registration,
+    )
+  };
+  if result == ERROR_SUCCESS {
+    Ok(())
+  } else {
+    Err(format!("PowerRegisterSuspendResumeNotification failed with {}", result.0))
+  }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn listen(_sender: UnboundedSender<PowerEvent>) -> Result<(), String> {
+  Err("not supported on this platform".to_string())
+}
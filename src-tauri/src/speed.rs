@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Speed is averaged over this much recent history
+const WINDOW: Duration = Duration::from_secs(5);
+
+// Sliding-window throughput estimate. Unlike total/elapsed it reacts within a
+// few seconds to speed changes, and a fresh meter per transfer attempt keeps
+// time spent paused out of the average.
+pub(crate) struct SpeedMeter {
+  samples: VecDeque<(Instant, u64)>,
+}
+
+impl SpeedMeter {
+  pub(crate) fn new() -> Self {
+    Self { samples: VecDeque::new() }
+  }
+
+  // Records the cumulative byte count at `now`.
+  pub(crate) fn record(&mut self, now: Instant, bytes: u64) {
+    self.samples.push_back((now, bytes));
+    // Keep one sample older than the window as the baseline
+    while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= WINDOW {
+      self.samples.pop_front();
+    }
+  }
+
+  pub(crate) fn bytes_per_sec(&self) -> f64 {
+    let (Some((start, start_bytes)), Some((end, end_bytes))) = (self.samples.front(), self.samples.back())
+    else {
+      return 0.0;
+    };
+    let elapsed = end.duration_since(*start).as_secs_f64();
+    if elapsed <= 0.0 {
+      return 0.0;
+    }
+    end_bytes.saturating_sub(*start_bytes) as f64 / elapsed
+  }
+
+  // Seconds until `total` is reached, or None when the size or speed is unknown.
+  pub(crate) fn eta_seconds(&self, downloaded: u64, total: Option<u64>) -> Option<u64> {
+    let speed = self.bytes_per_sec();
+    let total = total?;
+    if speed <= 0.0 {
+      return None;
+    }
+    Some((total.saturating_sub(downloaded) as f64 / speed).ceil() as u64)
+  }
+}
+
+// Seconds until a whole queue is done, or None when nothing is transferring.
+// Each `running` download, as (remaining bytes, speed), keeps its own speed;
+// the `queued` ones, by remaining bytes in queue order, take a slot as one
+// frees up, at most `limit` transferring at once, and are assumed to go at
+// the running downloads' average speed.
+pub(crate) fn queue_eta_seconds(running: &[(u64, f64)], queued: &[u64], limit: usize) -> Option<u64> {
+  let total_speed: f64 = running.iter().map(|(_, speed)| speed).sum();
+  if total_speed <= 0.0 {
+    return None;
+  }
+  let average = total_speed / running.len() as f64;
+  // When each slot is done with what it's been given so far, in seconds
+  let mut slots: Vec<f64> =
+    running.iter().map(|(remaining, speed)| if *speed > 0.0 { *remaining as f64 / speed } else { 0.0 }).collect();
+  let free = limit.min(running.len() + queued.len()).saturating_sub(running.len());
+  slots.extend(std::iter::repeat(0.0).take(free));
+  for remaining in queued {
+    let next = slots.iter_mut().min_by(|a, b| a.total_cmp(b))?;
+    *next += *remaining as f64 / average;
+  }
+  let last = slots.into_iter().fold(0.0, f64::max);
+  Some(last.ceil() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn queue_eta_waits_for_slots() {
+    assert_eq!(queue_eta_seconds(&[], &[100], 3), None);
+    assert_eq!(queue_eta_seconds(&[(100, 0.0)], &[], 3), None);
+    // 100 bytes left at 10/s and 200 at 20/s, then the queued 150 at 15/s
+    // in whichever slot frees first
+    assert_eq!(queue_eta_seconds(&[(100, 10.0), (200, 20.0)], &[150], 2), Some(20));
+    // A third slot starts it straight away
+    assert_eq!(queue_eta_seconds(&[(100, 10.0), (200, 20.0)], &[150], 3), Some(10));
+    assert_eq!(queue_eta_seconds(&[(10, 10.0)], &[10, 10, 10], 1), Some(4));
+  }
+}
@@ -0,0 +1,212 @@
+// The download map behind AppState::downloads. Every status change becomes a
+// "download://status" event, emitted when the lock that made the change is
+// released. Because that happens while the lock is still held, events go out
+// exactly once per change and in the order the changes were made, no matter
+// which command or task made them. The same release brings the map of every
+// download's current file up to date, so it follows renames, moves and
+// removals whoever makes them and can be read without waiting for the lock.
+// Only the downloads reached through get_mut, insert or remove are checked
+// then; borrowing the whole map mutably has every download checked.
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::lock::LockExt;
+use crate::{AppHandle, DownloadInfo};
+
+#[derive(Serialize, Clone)]
+struct StatusEvent<'a> {
+  id: &'a str,
+  // None for a download that was just added
+  old_status: Option<&'a str>,
+  new_status: &'a str,
+  // The failure, for transitions to "error"
+  error: Option<&'a str>,
+}
+
+pub(crate) struct Downloads {
+  inner: tokio::sync::Mutex<Inner>,
+  // Set once the app is running; changes before that aren't announced
+  app: OnceLock<AppHandle>,
+  // Where each download's file is on disk right now, see working_path
+  paths: Mutex<HashMap<String, PathBuf>>,
+}
+
+struct Inner {
+  downloads: HashMap<String, DownloadInfo>,
+  // Status of every download as last announced
+  announced: HashMap<String, String>,
+}
+
+pub(crate) struct DownloadsGuard<'a> {
+  inner: tokio::sync::MutexGuard<'a, Inner>,
+  app: Option<&'a AppHandle>,
+  paths: &'a Mutex<HashMap<String, PathBuf>>,
+  // Downloads that may have changed under this lock; None once the map itself
+  // was borrowed mutably, when any of them may have
+  touched: Option<HashSet<String>>,
+}
+
+impl Downloads {
+  pub(crate) fn new(downloads: HashMap<String, DownloadInfo>) -> Self {
+    let announced = downloads
+      .iter()
+      .map(|(id, download)| (id.clone(), download.status.clone()))
+      .collect();
+    let paths = downloads
+      .iter()
+      .map(|(id, download)| (id.clone(), download.working_path()))
+      .collect();
+    Self {
+      inner: tokio::sync::Mutex::new(Inner { downloads, announced }),
+      app: OnceLock::new(),
+      paths: Mutex::new(paths),
+    }
+  }
+
+  pub(crate) fn attach(&self, app: AppHandle) {
+    let _ = self.app.set(app);
+  }
+
+  pub(crate) async fn lock(&self) -> DownloadsGuard<'_> {
+    self.guard(self.inner.lock().await)
+  }
+
+  #[cfg(test)]
+  pub(crate) fn blocking_lock(&self) -> DownloadsGuard<'_> {
+    self.guard(self.inner.blocking_lock())
+  }
+
+  fn guard<'a>(&'a self, inner: tokio::sync::MutexGuard<'a, Inner>) -> DownloadsGuard<'a> {
+    DownloadsGuard { inner, app: self.app.get(), paths: &self.paths, touched: Some(HashSet::new()) }
+  }
+
+  // The file of a download as of the last released lock, None for unknown ids
+  pub(crate) fn path(&self, download_id: &str) -> Option<PathBuf> {
+    self.paths.lock_or_recover().get(download_id).cloned()
+  }
+}
+
+impl DownloadsGuard<'_> {
+  pub(crate) fn get_mut(&mut self, download_id: &str) -> Option<&mut DownloadInfo> {
+    self.touch(download_id);
+    self.inner.downloads.get_mut(download_id)
+  }
+
+  pub(crate) fn insert(&mut self, download_id: String, download: DownloadInfo) -> Option<DownloadInfo> {
+    self.touch(&download_id);
+    self.inner.downloads.insert(download_id, download)
+  }
+
+  pub(crate) fn remove(&mut self, download_id: &str) -> Option<DownloadInfo> {
+    self.touch(download_id);
+    self.inner.downloads.remove(download_id)
+  }
+
+  fn touch(&mut self, download_id: &str) {
+    if let Some(touched) = self.touched.as_mut().filter(|touched| !touched.contains(download_id)) {
+      touched.insert(download_id.to_string());
+    }
+  }
+}
+
+impl Deref for DownloadsGuard<'_> {
+  type Target = HashMap<String, DownloadInfo>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.inner.downloads
+  }
+}
+
+impl DerefMut for DownloadsGuard<'_> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    self.touched = None;
+    &mut self.inner.downloads
+  }
+}
+
+impl Drop for DownloadsGuard<'_> {
+  fn drop(&mut self) {
+    let Inner { downloads, announced } = &mut *self.inner;
+    let mut paths = self.paths.lock_or_recover();
+    let Some(touched) = &self.touched else {
+      announced.retain(|id, _| downloads.contains_key(id));
+      paths.retain(|id, _| downloads.contains_key(id));
+      for (id, download) in downloads.iter() {
+        sync(self.app, announced, &mut paths, id, download);
+      }
+      return;
+    };
+    for id in touched {
+      match downloads.get(id) {
+        Some(download) => sync(self.app, announced, &mut paths, id, download),
+        None => {
+          announced.remove(id);
+          paths.remove(id);
+        }
+      }
+    }
+  }
+}
+
+// Records where the download's file is and announces its status if it changed
+fn sync(
+  app: Option<&AppHandle>,
+  announced: &mut HashMap<String, String>,
+  paths: &mut HashMap<String, PathBuf>,
+  id: &str,
+  download: &DownloadInfo,
+) {
+  let path = download.working_path();
+  if paths.get(id) != Some(&path) {
+    paths.insert(id.to_string(), path);
+  }
+  let old_status = announced.get(id);
+  if old_status == Some(&download.status) {
+    return;
+  }
+  if let Some(app) = app {
+    let event = StatusEvent {
+      id,
+      old_status: old_status.map(String::as_str),
+      new_status: &download.status,
+      error: download.error.as_deref().filter(|_| download.status == "error"),
+    };
+    if let Err(e) = app.emit("download://status", event) {
+      log::warn!("Failed to emit status change for {}: {}", id, e);
+    }
+  }
+  announced.insert(id.to_string(), download.status.clone());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn released_locks_update_the_paths_they_touched() {
+    let download = |path: &str| DownloadInfo { path: PathBuf::from(path), ..Default::default() };
+    let downloads = Downloads::new(HashMap::from([("a".to_string(), download("/d/a.mp4"))]));
+    let block_on = tauri::async_runtime::block_on;
+
+    if let Some(a) = block_on(downloads.lock()).get_mut("a") {
+      a.part_file = true;
+    }
+    assert_eq!(downloads.path("a"), Some(PathBuf::from("/d/a.mp4.part")));
+
+    block_on(downloads.lock()).insert("b".to_string(), download("/d/b.mp4"));
+    assert_eq!(downloads.path("b"), Some(PathBuf::from("/d/b.mp4")));
+    block_on(downloads.lock()).remove("a");
+    assert_eq!(downloads.path("a"), None);
+
+    // Changes made through the map itself are found too
+    for b in block_on(downloads.lock()).values_mut() {
+      b.path = PathBuf::from("/e/b.mp4");
+    }
+    assert_eq!(downloads.path("b"), Some(PathBuf::from("/e/b.mp4")));
+  }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::DownloadError;
+
+// Free space below this is reported as quota_exceeded so the UI can warn early,
+// as is reaching the quota set with set_storage_quota
+pub(crate) const LOW_SPACE_THRESHOLD: u64 = 512 * 1024 * 1024;
+
+#[derive(Serialize)]
+pub(crate) struct StorageInfo {
+  // Bytes taken up by files in the downloads directory
+  pub used: u64,
+  // Free and total bytes of the volume holding the downloads directory
+  pub available: u64,
+  pub total: u64,
+  // Cap on `used` set with set_storage_quota, if any
+  pub quota: Option<u64>,
+  pub quota_exceeded: bool,
+  pub download_dir: String,
+}
+
+// Free bytes on the volume holding the directory, as seen by this user
+pub(crate) fn available_space(dir: &Path) -> Result<u64, String> {
+  fs2::available_space(dir).map_err(|e| format!("Failed to read available disk space: {}", e))
+}
+
+// Reads the volume statistics for the downloads directory, creating it first so
+// there is always a real path to query.
+pub(crate) fn storage_info(download_dir: &Path, quota: Option<u64>) -> Result<StorageInfo, String> {
+  std::fs::create_dir_all(download_dir)
+    .map_err(|e| format!("Failed to create downloads directory: {}", e))?;
+  let available = available_space(download_dir)?;
+  let total = fs2::total_space(download_dir)
+    .map_err(|e| format!("Failed to read total disk space: {}", e))?;
+  let used = used_space(download_dir)?;
+
+  Ok(StorageInfo {
+    used,
+    available,
+    total,
+    quota,
+    quota_exceeded: available < LOW_SPACE_THRESHOLD || quota.is_some_and(|quota| used >= quota),
+    download_dir: download_dir.display().to_string(),
+  })
+}
+
+// System locations downloads must never be written into, matched against the
+// directory and everything below it
+#[cfg(not(windows))]
+const PROTECTED_DIRS: &[&str] = &[
+  "/bin", "/boot", "/dev", "/etc", "/lib", "/lib64", "/proc", "/run", "/sbin", "/sys", "/usr",
+  "/System", "/Library", "/private/etc", "/private/var/db",
+];
+#[cfg(windows)]
+const PROTECTED_DIRS: &[&str] = &[r"C:\Windows", r"C:\Program Files", r"C:\Program Files (x86)", r"C:\ProgramData"];
+
+// Checks a user chosen downloads directory: it must be absolute, outside the
+// system locations above, a directory (created if missing) and writable.
+// Returns the canonical path. Missing write access is PermissionDenied, any
+// other problem InvalidInput.
+pub(crate) fn prepare_download_dir(dir: &Path) -> Result<PathBuf, DownloadError> {
+  if !dir.is_absolute() {
+    return Err(DownloadError::InvalidInput(format!(
+      "Downloads directory must be an absolute path: {}",
+      dir.display()
+    )));
+  }
+  std::fs::create_dir_all(dir).map_err(|e| invalid_dir(&format!("Failed to create {}", dir.display()), dir, e))?;
+  let dir = dir
+    .canonicalize()
+    .map_err(|e| invalid_dir(&format!("Failed to resolve {}", dir.display()), dir, e))?;
+  if !dir.is_dir() {
+    return Err(DownloadError::InvalidInput(format!("{} is not a directory", dir.display())));
+  }
+  if dir.parent().is_none() || is_protected(&dir) {
+    return Err(DownloadError::InvalidInput(format!(
+      "{} is a system location and can't hold downloads",
+      dir.display()
+    )));
+  }
+  check_writable(&dir)?;
+  Ok(dir)
+}
+
+// Creates the directory if missing and checks it can be written to. Creating
+// and removing a file is the only reliable test across platforms; permission
+// bits and ACLs each tell only part of the story.
+pub(crate) fn check_writable(dir: &Path) -> Result<(), DownloadError> {
+  std::fs::create_dir_all(dir).map_err(|e| invalid_dir(&format!("Failed to create {}", dir.display()), dir, e))?;
+  let probe = dir.join(format!(".stream-haven-write-test-{}", std::process::id()));
+  std::fs::write(&probe, b"").map_err(|e| invalid_dir(&format!("{} is not writable", dir.display()), dir, e))?;
+  let _ = std::fs::remove_file(&probe);
+  Ok(())
+}
+
+fn invalid_dir(context: &str, dir: &Path, e: std::io::Error) -> DownloadError {
+  match DownloadError::file(context, dir, e) {
+    DownloadError::Io(message) => DownloadError::InvalidInput(message),
+    err => err,
+  }
+}
+
+// Checks a file path picked in a save dialog. Its directory must pass
+// prepare_download_dir and its name must come through sanitizing unchanged.
+// A file already there is only accepted with `overwrite`, and never when it
+// is a directory or a symlink. Returns the path under the canonical directory.
+pub(crate) fn prepare_save_path(path: &Path, overwrite: bool) -> Result<PathBuf, DownloadError> {
+  let invalid = DownloadError::InvalidInput;
+  if !path.is_absolute() {
+    return Err(invalid(format!("Save path must be absolute: {}", path.display())));
+  }
+  let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+    return Err(invalid(format!("Save path must name a file: {}", path.display())));
+  };
+  let name = name.to_string_lossy();
+  if crate::filename::sanitize(&name).ok().as_deref() != Some(&*name) {
+    return Err(invalid(format!("{:?} can't be used as a file name", name)));
+  }
+  let path = prepare_download_dir(dir)?.join(&*name);
+  match std::fs::symlink_metadata(&path) {
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(path),
+    Err(e) => Err(invalid_dir(&format!("Failed to read {}", path.display()), &path, e)),
+    Ok(metadata) if !metadata.is_file() => Err(invalid(format!("{} exists and is not a regular file", path.display()))),
+    Ok(_) if !overwrite => Err(invalid(format!("{} already exists", path.display()))),
+    Ok(_) => Ok(path),
+  }
+}
+
+fn is_protected(dir: &Path) -> bool {
+  // Verbatim prefixes from canonicalize (\\?\C:\...) would never match otherwise
+  let display = dir.display().to_string();
+  let normalized = PathBuf::from(display.strip_prefix(r"\\?\").unwrap_or(&display));
+  PROTECTED_DIRS.iter().any(|protected| {
+    if cfg!(windows) {
+      let candidate = normalized.display().to_string().to_lowercase();
+      let protected = protected.to_lowercase();
+      candidate == protected || candidate.starts_with(&format!("{}\\", protected))
+    } else {
+      normalized.starts_with(protected)
+    }
+  })
+}
+
+// Moves a file, renaming when source and destination share a volume and
+// otherwise copying and deleting the original. progress gets the bytes copied
+// and the total after every chunk of a copy. A failed copy leaves the source
+// untouched and removes what was written.
+pub(crate) fn move_file(source: &Path, target: &Path, mut progress: impl FnMut(u64, u64)) -> Result<(), String> {
+  use std::io::{Read, Write};
+
+  match std::fs::rename(source, target) {
+    Ok(()) => return Ok(()),
+    Err(e) if !is_cross_device(&e) => {
+      return Err(format!("Failed to move {} to {}: {}", source.display(), target.display(), e));
+    }
+    Err(_) => {}
+  }
+
+  let mut copy = || -> std::io::Result<()> {
+    let mut input = std::fs::File::open(source)?;
+    let total = input.metadata()?.len();
+    let mut output = std::fs::File::create(target)?;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    let mut copied = 0;
+    loop {
+      let read = input.read(&mut buffer)?;
+      if read == 0 {
+        break;
+      }
+      output.write_all(&buffer[..read])?;
+      copied += read as u64;
+      progress(copied, total);
+    }
+    output.sync_all()
+  };
+  if let Err(e) = copy() {
+    let _ = std::fs::remove_file(target);
+    return Err(format!("Failed to copy {} to {}: {}", source.display(), target.display(), e));
+  }
+  std::fs::remove_file(source)
+    .map_err(|e| format!("Copied to {} but failed to remove {}: {}", target.display(), source.display(), e))
+}
+
+// Renames can't cross volumes: EXDEV on unix, ERROR_NOT_SAME_DEVICE on Windows
+fn is_cross_device(e: &std::io::Error) -> bool {
+  #[cfg(unix)]
+  const CROSS_DEVICE: i32 = libc::EXDEV;
+  #[cfg(windows)]
+  const CROSS_DEVICE: i32 = 17;
+  #[cfg(not(any(unix, windows)))]
+  const CROSS_DEVICE: i32 = -1;
+
+  e.raw_os_error() == Some(CROSS_DEVICE)
+}
+
+// Bytes taken up by the files in the downloads directory; zero if it doesn't
+// exist yet
+pub(crate) fn used_space(download_dir: &Path) -> Result<u64, String> {
+  match directory_size(download_dir) {
+    Ok(size) => Ok(size),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+    Err(e) => Err(format!("Failed to measure downloads directory: {}", e)),
+  }
+}
+
+// Sums the sizes of all regular files below the directory.
+fn directory_size(dir: &Path) -> std::io::Result<u64> {
+  let mut size = 0;
+  for entry in std::fs::read_dir(dir)? {
+    let entry = entry?;
+    let file_type = entry.file_type()?;
+    if file_type.is_dir() {
+      size += directory_size(&entry.path())?;
+    } else if file_type.is_file() {
+      size += entry.metadata()?.len();
+    }
+  }
+  Ok(size)
+}
+
+#[derive(Serialize, Default)]
+pub(crate) struct ClearSummary {
+  pub files_removed: u64,
+  pub bytes_removed: u64,
+  // Files left in place because a download is still writing to them
+  pub skipped: Vec<String>,
+  // Files that could not be deleted, with the reason
+  pub failed: Vec<String>,
+  // Symlinks left in place; what they point to is never followed or removed
+  pub symlinks: Vec<String>,
+}
+
+impl ClearSummary {
+  // Adds up the results of clearing another directory
+  pub(crate) fn absorb(&mut self, other: ClearSummary) {
+    self.files_removed += other.files_removed;
+    self.bytes_removed += other.bytes_removed;
+    self.skipped.extend(other.skipped);
+    self.failed.extend(other.failed);
+    self.symlinks.extend(other.symlinks);
+  }
+}
+
+// Deletes every regular file below the downloads directory except the ones in
+// `in_use`. Symlinks are reported rather than followed, and each candidate is
+// canonicalized and must still lie inside the directory, so nothing outside it
+// can ever be removed. Subdirectories left empty are removed too.
+pub(crate) fn clear_directory(download_dir: &Path, in_use: &[PathBuf]) -> Result<ClearSummary, String> {
+  let mut summary = ClearSummary::default();
+  let root = match download_dir.canonicalize() {
+    Ok(root) => root,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(summary),
+    Err(e) => return Err(format!("Failed to resolve downloads directory: {}", e)),
+  };
+  let in_use: HashSet<PathBuf> = in_use
+    .iter()
+    .filter_map(|path| path.canonicalize().ok())
+    .collect();
+
+  clear_recursive(&root, &root, &in_use, &mut summary)
+    .map_err(|e| format!("Failed to read downloads directory: {}", e))?;
+  Ok(summary)
+}
+
+fn clear_recursive(
+  root: &Path,
+  dir: &Path,
+  in_use: &HashSet<PathBuf>,
+  summary: &mut ClearSummary,
+) -> std::io::Result<()> {
+  for entry in std::fs::read_dir(dir)? {
+    let entry = entry?;
+    // file_type comes from the entry itself, so a symlink shows up as one
+    // instead of as whatever it points to
+    let file_type = entry.file_type()?;
+    if file_type.is_symlink() {
+      log::warn!("Leaving symlink {} in the downloads directory alone", entry.path().display());
+      summary.symlinks.push(entry.path().display().to_string());
+      continue;
+    }
+    let Ok(path) = entry.path().canonicalize() else { continue };
+    if !path.starts_with(root) {
+      log::warn!("Skipping {} outside the downloads directory", path.display());
+      continue;
+    }
+
+    if file_type.is_dir() {
+      clear_recursive(root, &path, in_use, summary)?;
+      // A folder downloads were sorted into goes once it is empty; one still
+      // holding a file in use stays
+      let _ = std::fs::remove_dir(&path);
+    } else if file_type.is_file() {
+      if in_use.contains(&path) {
+        summary.skipped.push(path.display().to_string());
+        continue;
+      }
+      let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+      match std::fs::remove_file(&path) {
+        Ok(()) => {
+          summary.files_removed += 1;
+          summary.bytes_removed += size;
+        }
+        Err(e) => summary.failed.push(format!("{}: {}", path.display(), e)),
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn clearing_does_not_follow_symlinks() {
+    let base = std::env::temp_dir().join(format!("stream-haven-clear-{}", std::process::id()));
+    let (dir, outside) = (base.join("downloads"), base.join("outside"));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(dir.join("a.bin"), b"abc").unwrap();
+    std::fs::write(outside.join("keep.bin"), b"keep").unwrap();
+    std::os::unix::fs::symlink(outside.join("keep.bin"), dir.join("link.bin")).unwrap();
+    std::os::unix::fs::symlink(&outside, dir.join("linked-dir")).unwrap();
+
+    let summary = clear_directory(&dir, &[]).unwrap();
+    assert_eq!((summary.files_removed, summary.bytes_removed), (1, 3));
+    assert_eq!(summary.symlinks.len(), 2);
+    assert!(!dir.join("a.bin").exists());
+    assert_eq!(std::fs::read(outside.join("keep.bin")).unwrap(), b"keep");
+    assert!(std::fs::symlink_metadata(dir.join("link.bin")).is_ok());
+    let _ = std::fs::remove_dir_all(&base);
+  }
+
+  #[test]
+  fn clearing_removes_emptied_subdirectories() {
+    let dir = std::env::temp_dir().join(format!("stream-haven-clear-nested-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("Movies")).unwrap();
+    std::fs::create_dir_all(dir.join("Music").join("Live")).unwrap();
+    std::fs::write(dir.join("Movies").join("film.mp4"), b"abc").unwrap();
+    std::fs::write(dir.join("Music").join("Live").join("track.mp3"), b"ab").unwrap();
+    let in_use = dir.join("Music").join("Live").join("track.mp3");
+
+    let summary = clear_directory(&dir, &[in_use.clone()]).unwrap();
+    assert_eq!((summary.files_removed, summary.skipped.len()), (1, 1));
+    assert!(!dir.join("Movies").exists());
+    assert!(in_use.exists());
+    assert!(dir.is_dir());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
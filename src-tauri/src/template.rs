@@ -0,0 +1,125 @@
+// Output file name templates set with set_filename_template, such as
+// "{date}_{host}_{name}.{ext}". Placeholders that describe the download
+// ({host}, {date}, {index}, {hash8}) are filled in when it is queued; the
+// result is kept on the download as its pattern, so {name} and {ext} can be
+// filled in again when the server later announces a better name. Rendered
+// names still go through filename::sanitize and the collision numbering.
+use sha2::{Digest, Sha256};
+
+const PLACEHOLDERS: &[&str] = &["name", "ext", "host", "date", "index", "hash8"];
+// Placeholders that tell downloads apart; every template needs one
+const DISTINGUISHING: &[&str] = &["name", "index", "hash8"];
+const MAX_TEMPLATE_LEN: usize = 200;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FilenameTemplate(String);
+
+// What a template's download-level placeholders are filled with
+pub(crate) struct Fields<'a> {
+  pub url: &'a str,
+  // Position the download takes in the list, counting from 1
+  pub index: usize,
+  pub date: chrono::NaiveDate,
+}
+
+impl FilenameTemplate {
+  pub(crate) fn parse(template: &str) -> Result<Self, String> {
+    let template = template.trim();
+    if template.is_empty() || template.len() > MAX_TEMPLATE_LEN {
+      return Err(format!("Templates must be 1 to {} bytes long", MAX_TEMPLATE_LEN));
+    }
+    if template.contains(['/', '\\']) {
+      return Err("Templates can't contain path separators".to_string());
+    }
+    let names = placeholders(template)?;
+    if let Some(unknown) = names.iter().find(|name| !PLACEHOLDERS.contains(name)) {
+      return Err(format!(
+        "Unknown placeholder {{{}}}; use {}",
+        unknown,
+        PLACEHOLDERS.iter().map(|name| format!("{{{}}}", name)).collect::<Vec<_>>().join(", ")
+      ));
+    }
+    if !names.iter().any(|name| DISTINGUISHING.contains(name)) {
+      return Err("Templates need {name}, {index} or {hash8} so files get different names".to_string());
+    }
+    Ok(Self(template.to_string()))
+  }
+
+  // The template with everything but {name} and {ext} filled in
+  pub(crate) fn bind(&self, fields: &Fields<'_>) -> String {
+    let host = url::Url::parse(fields.url)
+      .ok()
+      .and_then(|url| url.host_str().map(crate::hosts::normalize))
+      .unwrap_or_default();
+    let hash = hex::encode(Sha256::digest(fields.url.as_bytes()));
+    self
+      .0
+      .replace("{host}", &host)
+      .replace("{date}", &fields.date.format("%Y-%m-%d").to_string())
+      .replace("{index}", &format!("{:04}", fields.index))
+      .replace("{hash8}", &hash[..8])
+  }
+}
+
+// Fills a bound pattern with the stem and extension of `name`. Without an
+// extension, a "." written right before {ext} is dropped as well.
+pub(crate) fn apply(pattern: &str, name: &str) -> Result<String, String> {
+  let (stem, extension) = match name.rsplit_once('.') {
+    Some((stem, extension)) if !stem.is_empty() => (stem, extension),
+    _ => (name, ""),
+  };
+  let mut rendered = pattern.to_string();
+  if extension.is_empty() {
+    rendered = rendered.replace(".{ext}", "");
+  }
+  rendered = rendered.replace("{name}", stem).replace("{ext}", extension);
+  crate::filename::sanitize(&rendered)
+}
+
+// The placeholder names in a template, refusing unbalanced braces
+fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+  let mut names = Vec::new();
+  let mut rest = template;
+  while let Some(open) = rest.find(['{', '}']) {
+    if rest[open..].starts_with('}') {
+      return Err("Unmatched } in template".to_string());
+    }
+    let close = rest[open..].find('}').ok_or("Unmatched { in template")? + open;
+    let name = &rest[open + 1..close];
+    if name.contains('{') {
+      return Err("Unmatched { in template".to_string());
+    }
+    names.push(name);
+    rest = &rest[close + 1..];
+  }
+  Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn renders_and_validates_templates() {
+    let template = FilenameTemplate::parse("{date}_{host}_{index}_{name}.{ext}").unwrap();
+    let fields = Fields {
+      url: "https://CDN.example.com/media/clip.mp4",
+      index: 7,
+      date: chrono::NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+    };
+    let pattern = template.bind(&fields);
+    assert_eq!(pattern, "2024-03-09_cdn.example.com_0007_{name}.{ext}");
+    assert_eq!(apply(&pattern, "clip.mp4").unwrap(), "2024-03-09_cdn.example.com_0007_clip.mp4");
+    assert_eq!(apply(&pattern, "README").unwrap(), "2024-03-09_cdn.example.com_0007_README");
+    assert_eq!(apply(&pattern, "a:b.txt").unwrap(), "2024-03-09_cdn.example.com_0007_a_b.txt");
+    let hashed = FilenameTemplate::parse("{hash8}").unwrap().bind(&fields);
+    assert_eq!(hashed.len(), 8);
+
+    assert!(FilenameTemplate::parse("{date}_{host}").is_err());
+    assert!(FilenameTemplate::parse("{name}_{size}").is_err());
+    assert!(FilenameTemplate::parse("{name").is_err());
+    assert!(FilenameTemplate::parse("name}").is_err());
+    assert!(FilenameTemplate::parse("{host}/{name}").is_err());
+    assert!(FilenameTemplate::parse("").is_err());
+  }
+}
@@ -0,0 +1,65 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::lock::LockExt;
+
+// Shared token bucket that keeps the combined throughput of all downloads
+// under an optional cap. Each chunk takes its size in tokens; a chunk that
+// overdraws the bucket sleeps for exactly as long as the debt takes to refill.
+// Later callers see a deeper debt and wait longer, so concurrent downloads are
+// served roughly in arrival order.
+pub(crate) struct Throttle {
+  bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+  // Bytes per second, None when unlimited
+  rate: Option<u64>,
+  tokens: f64,
+  last_refill: Instant,
+}
+
+impl Throttle {
+  pub(crate) fn new(rate: Option<u64>) -> Self {
+    Self {
+      bucket: Mutex::new(Bucket {
+        rate,
+        tokens: 0.0,
+        last_refill: Instant::now(),
+      }),
+    }
+  }
+
+  pub(crate) fn set_rate(&self, rate: Option<u64>) {
+    let mut bucket = self.bucket.lock_or_recover();
+    bucket.rate = rate;
+    bucket.tokens = 0.0;
+    bucket.last_refill = Instant::now();
+  }
+
+  pub(crate) fn rate(&self) -> Option<u64> {
+    self.bucket.lock_or_recover().rate
+  }
+
+  // Waits until `bytes` may be written without exceeding the rate.
+  pub(crate) async fn acquire(&self, bytes: usize) {
+    let wait = {
+      let mut bucket = self.bucket.lock_or_recover();
+      let Some(rate) = bucket.rate.filter(|rate| *rate > 0) else { return };
+      let rate = rate as f64;
+
+      // Allow at most one second worth of burst after an idle period
+      let now = Instant::now();
+      let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+      bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+      bucket.last_refill = now;
+
+      bucket.tokens -= bytes as f64;
+      if bucket.tokens >= 0.0 {
+        return;
+      }
+      Duration::from_secs_f64(-bucket.tokens / rate)
+    };
+    tokio::time::sleep(wait).await;
+  }
+}
@@ -0,0 +1,68 @@
+// Trust settings for the download client, for servers behind a private CA.
+// The certificates are added on top of the system store, never instead of it.
+use std::error::Error;
+
+#[derive(Clone, Default)]
+pub(crate) struct TlsOptions {
+  ca_certs: Vec<reqwest::Certificate>,
+  // Skips certificate and host name validation entirely. Off unless the user
+  // turned it on with set_tls_options.
+  accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+  // Parses every PEM input, each of which may hold a bundle of certificates.
+  // Any input that doesn't parse fails the whole call.
+  pub(crate) fn new(ca_certs: &[String], accept_invalid_certs: bool) -> Result<Self, String> {
+    let mut parsed = Vec::new();
+    for (index, pem) in ca_certs.iter().enumerate() {
+      let certs = reqwest::Certificate::from_pem_bundle(pem.trim().as_bytes())
+        .map_err(|e| format!("CA certificate {} is not valid PEM: {}", index + 1, describe(&e)))?;
+      if certs.is_empty() {
+        return Err(format!(
+          "CA certificate {} contains no \"BEGIN CERTIFICATE\" block",
+          index + 1
+        ));
+      }
+      parsed.extend(certs);
+    }
+    Ok(Self { ca_certs: parsed, accept_invalid_certs })
+  }
+
+  pub(crate) fn ca_cert_count(&self) -> usize {
+    self.ca_certs.len()
+  }
+
+  pub(crate) fn accepts_invalid_certs(&self) -> bool {
+    self.accept_invalid_certs
+  }
+
+  pub(crate) fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    for cert in &self.ca_certs {
+      builder = builder.add_root_certificate(cert.clone());
+    }
+    builder.danger_accept_invalid_certs(self.accept_invalid_certs)
+  }
+}
+
+// reqwest reports parse failures as "builder error"; the cause is in the source
+fn describe(e: &reqwest::Error) -> String {
+  match e.source() {
+    Some(source) => source.to_string(),
+    None => e.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rejects_input_without_certificates() {
+    assert_eq!(TlsOptions::new(&[], false).unwrap().ca_cert_count(), 0);
+    let error = TlsOptions::new(&["not a certificate".to_string()], false).err().unwrap();
+    assert!(error.contains("CA certificate 1"));
+    let truncated = "-----BEGIN CERTIFICATE-----\nMIIB\n".to_string();
+    assert!(TlsOptions::new(&[truncated], false).is_err());
+  }
+}
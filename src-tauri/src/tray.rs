@@ -0,0 +1,145 @@
+// The system tray icon. Its tooltip sums up the running downloads ("3 active,
+// 64%") and its menu pauses or resumes everything without opening the window.
+// The tooltip follows the progress and status events, so it is only
+// refreshed while something changes, and at most once per REFRESH_INTERVAL.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
+use tauri::{Listener, Manager};
+
+use crate::{AppHandle, AppState, DownloadInfo, Runtime};
+
+const TRAY_ID: &str = "downloads";
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) fn setup(app: &AppHandle) -> tauri::Result<()> {
+  let menu = Menu::with_items(
+    app,
+    &[
+      &MenuItem::with_id(app, "pause_all", "Pause all", true, None::<&str>)?,
+      &MenuItem::with_id(app, "resume_all", "Resume all", true, None::<&str>)?,
+      &PredefinedMenuItem::separator(app)?,
+      &MenuItem::with_id(app, "show", "Open StreamHaven", true, None::<&str>)?,
+      &MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?,
+    ],
+  )?;
+  let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+    .tooltip(summary(&HashMap::new()))
+    .menu(&menu)
+    .show_menu_on_left_click(false)
+    .on_menu_event(on_menu_event)
+    .on_tray_icon_event(|tray, event| {
+      if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+        show_main_window(tray.app_handle());
+      }
+    });
+  if let Some(icon) = app.default_window_icon() {
+    builder = builder.icon(icon.clone());
+  }
+  let tray = builder.build(app)?;
+
+  let changed = Arc::new(tokio::sync::Notify::new());
+  for event in ["download://progress", "download://status"] {
+    let changed = changed.clone();
+    app.listen_any(event, move |_| changed.notify_one());
+  }
+  tauri::async_runtime::spawn(refresh(app.clone(), tray, changed));
+  Ok(())
+}
+
+// Rewrites the tooltip after each burst of changes. A change that comes in
+// while waiting out the interval is picked up by the next pass.
+async fn refresh(app: AppHandle, tray: TrayIcon<Runtime>, changed: Arc<tokio::sync::Notify>) {
+  loop {
+    changed.notified().await;
+    let tooltip = summary(&*app.state::<AppState>().downloads.lock().await);
+    if let Err(e) = tray.set_tooltip(Some(tooltip)) {
+      log::warn!("Failed to update the tray tooltip: {}", e);
+    }
+    tokio::time::sleep(REFRESH_INTERVAL).await;
+  }
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+  match event.id().as_ref() {
+    "pause_all" => {
+      let app = app.clone();
+      tauri::async_runtime::spawn(async move {
+        if let Err(e) = crate::pause_all(app.state()).await {
+          log::warn!("Pausing from the tray failed: {}", e);
+        }
+      });
+    }
+    "resume_all" => {
+      let app = app.clone();
+      tauri::async_runtime::spawn(async move {
+        if let Err(e) = crate::resume_all(app.clone(), app.state()).await {
+          log::warn!("Resuming from the tray failed: {}", e);
+        }
+      });
+    }
+    "show" => show_main_window(app),
+    "quit" => app.exit(0),
+    _ => {}
+  }
+}
+
+fn show_main_window(app: &AppHandle) {
+  let Some(window) = app.get_webview_window("main") else {
+    return;
+  };
+  let _ = window.unminimize();
+  let _ = window.show();
+  let _ = window.set_focus();
+}
+
+// "3 active, 64%", where the percentage covers the active downloads whose
+// size is known. It is left out when none of them has a known size.
+fn summary(downloads: &HashMap<String, DownloadInfo>) -> String {
+  let active: Vec<&DownloadInfo> = downloads.values().filter(|download| download.is_in_progress()).collect();
+  if active.is_empty() {
+    return "No active downloads".to_string();
+  }
+  let (done, total) = active
+    .iter()
+    .filter_map(|download| download.total_bytes.map(|total| (download.bytes_downloaded.min(total), total)))
+    .fold((0u64, 0u64), |(done, total), (d, t)| (done + d, total + t));
+  match (done * 100).checked_div(total) {
+    Some(percent) => format!("{} active, {}%", active.len(), percent),
+    None => format!("{} active", active.len()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn summary_covers_active_downloads_only() {
+    let mut downloads = HashMap::new();
+    assert_eq!(summary(&downloads), "No active downloads");
+    let entries = [
+      ("a", "downloading", 30, Some(100)),
+      ("b", "queued", 0, None),
+      ("c", "downloading", 98, Some(100)),
+      ("d", "completed", 500, Some(500)),
+    ];
+    for (id, status, bytes_downloaded, total_bytes) in entries {
+      let download = DownloadInfo {
+        id: id.to_string(),
+        status: status.to_string(),
+        bytes_downloaded,
+        total_bytes,
+        ..Default::default()
+      };
+      downloads.insert(id.to_string(), download);
+    }
+    assert_eq!(summary(&downloads), "3 active, 64%");
+    downloads.remove("a");
+    downloads.remove("c");
+    assert_eq!(summary(&downloads), "1 active");
+  }
+}
@@ -0,0 +1,156 @@
+// Bytes downloaded per local calendar day, for users on capped data plans.
+// Every chunk written by a download is counted towards the day it arrived
+// on, in the local time zone, so a transfer running past midnight is split
+// across both days. The counters are kept in usage.json next to the queue,
+// written every SAVE_INTERVAL while they change and once more on quit.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::lock::LockExt;
+use crate::{AppHandle, AppState};
+
+pub(crate) const USAGE_FILE: &str = "usage.json";
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+// Days kept; older ones are dropped when the counters are saved
+const RETENTION_DAYS: i64 = 400;
+
+// Which days get_bandwidth_usage adds up
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UsageRange {
+  Today,
+  // The current calendar month
+  Month,
+  // Today and the 29 days before it
+  Last30Days,
+  All,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct DayUsage {
+  // Local date as YYYY-MM-DD
+  pub date: NaiveDate,
+  pub bytes: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct UsageReport {
+  pub total_bytes: u64,
+  // Days with traffic in the range, oldest first
+  pub days: Vec<DayUsage>,
+}
+
+pub(crate) struct Usage {
+  path: PathBuf,
+  days: Mutex<BTreeMap<NaiveDate, u64>>,
+  // Set by every count since the last save
+  dirty: AtomicBool,
+}
+
+impl Usage {
+  // Loads the counters, starting from zero when the file is missing or corrupt
+  pub(crate) fn load(path: PathBuf) -> Self {
+    let days = match std::fs::read_to_string(&path) {
+      Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+        log::warn!("Ignoring corrupt bandwidth usage {}: {}", path.display(), e);
+        BTreeMap::new()
+      }),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+      Err(e) => {
+        log::warn!("Failed to read {}: {}", path.display(), e);
+        BTreeMap::new()
+      }
+    };
+    Self { path, days: Mutex::new(days), dirty: AtomicBool::new(false) }
+  }
+
+  // Counts bytes just written towards today
+  pub(crate) fn record(&self, bytes: u64) {
+    let today = chrono::Local::now().date_naive();
+    *self.days.lock_or_recover().entry(today).or_insert(0) += bytes;
+    self.dirty.store(true, Ordering::Relaxed);
+  }
+
+  pub(crate) fn report(&self, range: UsageRange) -> UsageReport {
+    report(&self.days.lock_or_recover(), range, chrono::Local::now().date_naive())
+  }
+
+  // Writes the counters if they changed since the last save
+  pub(crate) fn save(&self) {
+    if !self.dirty.swap(false, Ordering::Relaxed) {
+      return;
+    }
+    let json = {
+      let mut days = self.days.lock_or_recover();
+      let cutoff = chrono::Local::now().date_naive() - chrono::Duration::days(RETENTION_DAYS);
+      days.retain(|date, _| *date > cutoff);
+      serde_json::to_string_pretty(&*days)
+    };
+    let result = json
+      .map_err(|e| e.to_string())
+      .and_then(|json| write(&self.path, json.as_bytes()).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+      self.dirty.store(true, Ordering::Relaxed);
+      log::warn!("Failed to save bandwidth usage to {}: {}", self.path.display(), e);
+    }
+  }
+}
+
+// Saves the counters periodically while the app runs
+pub(crate) async fn watch(app: AppHandle) {
+  loop {
+    tokio::time::sleep(SAVE_INTERVAL).await;
+    app.state::<AppState>().usage.save();
+  }
+}
+
+fn report(days: &BTreeMap<NaiveDate, u64>, range: UsageRange, today: NaiveDate) -> UsageReport {
+  let first = match range {
+    UsageRange::Today => today,
+    UsageRange::Month => today.with_day(1).unwrap_or(today),
+    UsageRange::Last30Days => today - chrono::Duration::days(29),
+    UsageRange::All => NaiveDate::MIN,
+  };
+  let days: Vec<DayUsage> = days
+    .range(first..=today)
+    .map(|(date, bytes)| DayUsage { date: *date, bytes: *bytes })
+    .collect();
+  UsageReport { total_bytes: days.iter().map(|day| day.bytes).sum(), days }
+}
+
+fn write(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+  let tmp = path.with_extension("json.tmp");
+  std::fs::write(&tmp, contents)?;
+  std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn adds_up_days_in_range() {
+    let date = |day: &str| day.parse::<NaiveDate>().unwrap();
+    let days = BTreeMap::from([
+      (date("2024-02-28"), 100),
+      (date("2024-02-29"), 200),
+      (date("2024-03-01"), 30),
+      (date("2024-03-09"), 4),
+    ]);
+    let today = date("2024-03-09");
+    assert_eq!(report(&days, UsageRange::Today, today).total_bytes, 4);
+    assert_eq!(report(&days, UsageRange::Month, today).total_bytes, 34);
+    assert_eq!(report(&days, UsageRange::Last30Days, today).total_bytes, 334);
+    let all = report(&days, UsageRange::All, date("2024-03-01"));
+    assert_eq!(all.total_bytes, 330);
+    assert_eq!(all.days.first(), Some(&DayUsage { date: date("2024-02-28"), bytes: 100 }));
+    assert_eq!(all.days.len(), 3);
+  }
+}